@@ -0,0 +1,230 @@
+use crate::{Group, ParseError, ParseOptions, TagParser};
+
+/// The bytes every binary tag file starts with, followed by the format version.
+const MAGIC: &[u8; 4] = b"TAGP";
+/// The version of the binary format written by [`TagParser::to_bytes`].
+const VERSION: u8 = 1;
+
+impl TagParser {
+    /// Encodes the groups in a compact binary format, for fast reloading.
+    ///
+    /// Every group's name, aliases, tags, description, priority and depth are stored. Strings and
+    /// lists are prefixed with their length as a little-endian `u32`. Spans and raw blocks are
+    /// not stored. Use [`TagParser::from_bytes_compact`] to read the result back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of groups, a group's depth, the length of a list of aliases or
+    /// tags, or the length in bytes of a string is more than `u32::MAX`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\nblue");
+    /// let bytes = parser.to_bytes();
+    /// let loaded = TagParser::from_bytes_compact(&bytes).unwrap();
+    /// assert_eq!(loaded.groups()[0].tags, vec!["red", "blue"]);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        write_len(&mut bytes, self.groups.len());
+
+        for group in &self.groups {
+            write_str(&mut bytes, &group.name);
+            write_strs(&mut bytes, &group.aliases);
+            write_strs(&mut bytes, &group.tags);
+
+            match &group.description {
+                Some(description) => {
+                    bytes.push(1);
+                    write_str(&mut bytes, description);
+                }
+                None => bytes.push(0),
+            }
+            match group.priority {
+                Some(priority) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&priority.to_le_bytes());
+                }
+                None => bytes.push(0),
+            }
+            write_len(&mut bytes, group.depth);
+        }
+
+        bytes
+    }
+
+    /// Creates a new `TagParser` instance from bytes written by [`TagParser::to_bytes`].
+    ///
+    /// The parser uses the default options, and its data is the groups written in the tag file
+    /// format.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes`: The encoded groups.
+    ///
+    /// returns: A new `TagParser` instance, or [`ParseError::InvalidBinary`] if the bytes are
+    /// not a complete binary tag file.
+    pub fn from_bytes_compact(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC || reader.take(1)? != [VERSION] {
+            return Err(ParseError::InvalidBinary);
+        }
+
+        let count = reader.len()?;
+        let mut groups = Vec::new();
+        for _ in 0..count {
+            let name = reader.string()?;
+            let aliases = reader.strings()?;
+            let tags = reader.strings()?;
+            let description = if reader.flag()? {
+                Some(reader.string()?)
+            } else {
+                None
+            };
+            let priority = if reader.flag()? {
+                Some(i32::from_le_bytes(reader.array()?))
+            } else {
+                None
+            };
+            let depth = reader.len()?;
+
+            groups.push(Group {
+                name,
+                tags,
+                aliases,
+                description,
+                priority,
+                depth,
+                ..Default::default()
+            });
+        }
+
+        if !reader.bytes.is_empty() {
+            return Err(ParseError::InvalidBinary);
+        }
+
+        Ok(Self::from_groups(groups, ParseOptions::default()))
+    }
+}
+
+/// Writes a length as a little-endian `u32`.
+fn write_len(bytes: &mut Vec<u8>, len: usize) {
+    let len = u32::try_from(len).expect("length does not fit in the binary format");
+    bytes.extend_from_slice(&len.to_le_bytes());
+}
+
+/// Writes a length-prefixed string.
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_len(bytes, value.len());
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+/// Writes a length-prefixed list of strings.
+fn write_strs(bytes: &mut Vec<u8>, values: &[String]) {
+    write_len(bytes, values.len());
+    for value in values {
+        write_str(bytes, value);
+    }
+}
+
+/// Reads values from the front of a byte slice.
+struct Reader<'a> {
+    /// The bytes that have not been read yet.
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Reads the next `len` bytes.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        if self.bytes.len() < len {
+            return Err(ParseError::InvalidBinary);
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    /// Reads the next `N` bytes as an array.
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    /// Reads a byte that must be 0 or 1.
+    fn flag(&mut self) -> Result<bool, ParseError> {
+        match self.array()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(ParseError::InvalidBinary),
+        }
+    }
+
+    /// Reads a length written by `write_len`.
+    fn len(&mut self) -> Result<usize, ParseError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    /// Reads a string written by `write_str`.
+    fn string(&mut self) -> Result<String, ParseError> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| ParseError::InvalidBinary)
+    }
+
+    /// Reads a list of strings written by `write_strs`.
+    fn strings(&mut self) -> Result<Vec<String>, ParseError> {
+        let count = self.len()?;
+        // Every string takes at least four bytes, so a larger count cannot be valid
+        if count > self.bytes.len() / 4 {
+            return Err(ParseError::InvalidBinary);
+        }
+
+        (0..count).map(|_| self.string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_round_trip_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        parser.groups[1].description = Some("Numbers".to_string());
+        parser.groups[1].aliases = vec!["Numbers".to_string()];
+        parser.groups[2].priority = Some(-3);
+
+        let bytes = parser.to_bytes();
+        let loaded = TagParser::from_bytes_compact(&bytes).unwrap();
+        assert_eq!(loaded.groups().len(), parser.groups().len());
+        for (loaded, original) in loaded.groups().iter().zip(parser.groups()) {
+            assert_eq!(
+                *loaded,
+                Group {
+                    spans: Vec::new(),
+                    ..original.clone()
+                }
+            );
+        }
+        assert_eq!(loaded.to_bytes(), bytes);
+
+        assert_eq!(
+            TagParser::from_bytes_compact(&bytes[..bytes.len() - 1]).err(),
+            Some(ParseError::InvalidBinary)
+        );
+        assert_eq!(
+            TagParser::from_bytes_compact(data.as_bytes()).err(),
+            Some(ParseError::InvalidBinary)
+        );
+        assert!(
+            TagParser::from_bytes_compact(&TagParser::from("").to_bytes())
+                .unwrap()
+                .groups()
+                .is_empty()
+        );
+    }
+}
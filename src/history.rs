@@ -0,0 +1,280 @@
+use crate::{Group, TagParser};
+
+/// A recorded mutation, holding what is needed to revert it.
+#[derive(Debug, Clone)]
+pub(crate) enum Change {
+    /// A tag was inserted into a group.
+    AddTag { group: usize, index: usize },
+    /// A tag was removed from a group.
+    RemoveTag {
+        group: usize,
+        index: usize,
+        tag: String,
+    },
+    /// A group was appended at the given index.
+    AddGroup { index: usize },
+    /// Groups were removed, listed by their former index in ascending order.
+    RemoveGroups { groups: Vec<(usize, Group)> },
+    /// A group was moved from one index to another.
+    MoveGroup { from: usize, to: usize },
+    /// The tag lists of some groups were replaced, holding the previous lists.
+    ReplaceTags { tags: Vec<(usize, Vec<String>)> },
+    /// The groups were reordered, so that the group at index `i` came from index `order[i]`.
+    Reorder { order: Vec<usize> },
+}
+
+impl TagParser {
+    /// Turns recording of changes for [`TagParser::undo`] on or off.
+    ///
+    /// Changes made through [`TagParser::add_tag`], [`TagParser::remove_tag`],
+    /// [`TagParser::set_tags`], [`TagParser::truncate_groups`], [`TagParser::remove_group`],
+    /// [`TagParser::rename_tag`], [`TagParser::expand_synonyms`], [`TagParser::resolve_includes`],
+    /// [`TagParser::move_group`], [`TagParser::prune_empty_groups`],
+    /// [`TagParser::collapse_empty_runs`] and [`TagParser::sort_by_priority`] are recorded.
+    /// Turning recording off, parsing again, or changing the groups in a way that cannot be
+    /// undone, like [`TagParser::iter_mut`], [`TagParser::parse_additional`],
+    /// [`TagParser::reparse_line`] and [`TagParser::rebuild_data`], discards the recorded changes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Generic]\nred_hair");
+    /// parser.record_history(true);
+    /// parser.add_tag("Generic", "smile");
+    /// assert!(parser.undo());
+    /// assert_eq!(parser.groups()[0].tags, vec!["red_hair"]);
+    /// ```
+    pub fn record_history(&mut self, enabled: bool) {
+        self.history = enabled.then(Vec::new);
+    }
+
+    /// Reverts the last recorded change.
+    ///
+    /// returns: `true` if a change was reverted, `false` if there was nothing to undo. A change
+    /// that no longer fits the groups is not reverted, and the rest of the recorded changes are
+    /// discarded with it.
+    pub fn undo(&mut self) -> bool {
+        let Some(change) = self.history.as_mut().and_then(Vec::pop) else {
+            return false;
+        };
+
+        if self.revert(change) {
+            true
+        } else {
+            self.clear_history();
+            false
+        }
+    }
+
+    /// Reverts a change, or returns `false` without changing anything if its indices are out
+    /// of range for the current groups.
+    fn revert(&mut self, change: Change) -> bool {
+        let len = self.groups.len();
+        match change {
+            Change::AddTag { group, index } => {
+                let Some(tags) = self
+                    .groups
+                    .get_mut(group)
+                    .map(|group| &mut group.tags)
+                    .filter(|tags| index < tags.len())
+                else {
+                    return false;
+                };
+                tags.remove(index);
+            }
+            Change::RemoveTag { group, index, tag } => {
+                let Some(tags) = self
+                    .groups
+                    .get_mut(group)
+                    .map(|group| &mut group.tags)
+                    .filter(|tags| index <= tags.len())
+                else {
+                    return false;
+                };
+                tags.insert(index, tag);
+            }
+            Change::AddGroup { index } => {
+                if index >= len {
+                    return false;
+                }
+                self.groups.remove(index);
+            }
+            Change::RemoveGroups { groups } => {
+                // Each index counts the groups inserted before it
+                let fits = groups
+                    .iter()
+                    .enumerate()
+                    .all(|(inserted, (index, _))| *index <= len + inserted);
+                if !fits {
+                    return false;
+                }
+                for (index, group) in groups {
+                    self.groups.insert(index, group);
+                }
+            }
+            Change::MoveGroup { from, to } => {
+                if to >= len || from >= len {
+                    return false;
+                }
+                let group = self.groups.remove(to);
+                self.groups.insert(from, group);
+            }
+            Change::ReplaceTags { tags } => {
+                if tags.iter().any(|(group, _)| *group >= len) {
+                    return false;
+                }
+                for (group, tags) in tags {
+                    self.groups[group].tags = tags;
+                }
+            }
+            Change::Reorder { order } => {
+                let mut seen = vec![false; len];
+                let permutation = order.len() == len
+                    && order
+                        .iter()
+                        .all(|&index| index < len && !std::mem::replace(&mut seen[index], true));
+                if !permutation {
+                    return false;
+                }
+
+                let mut groups: Vec<Option<Group>> = vec![None; len];
+                for (group, index) in self.groups.drain(..).zip(order) {
+                    groups[index] = Some(group);
+                }
+                self.groups = groups.into_iter().flatten().collect();
+            }
+        }
+
+        true
+    }
+
+    /// Records a change if history recording is enabled.
+    pub(crate) fn record(&mut self, change: Change) {
+        if let Some(history) = &mut self.history {
+            history.push(change);
+        }
+    }
+
+    /// Discards the recorded changes, keeping recording enabled if it was.
+    pub(crate) fn clear_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        parser.add_tag("Generic", "untracked");
+        assert!(!parser.undo());
+
+        parser.record_history(true);
+        assert!(!parser.undo());
+        let original = parser.groups().clone();
+
+        assert!(parser.add_tag("IDs", "555"));
+        assert_eq!(parser.groups()[1].tags, vec!["102349", "555"]);
+        assert!(parser.undo());
+        assert_eq!(parser.groups()[1].tags, vec!["102349"]);
+
+        let removed = parser.remove_group("Generic").unwrap();
+        assert_eq!(removed.name, "Generic");
+        assert_eq!(parser.groups().len(), 2);
+        assert!(parser.undo());
+        assert_eq!(parser.groups().len(), 3);
+        assert_eq!(parser.groups()[0], original[0]);
+
+        parser.rename_tag("102349", "1", false);
+        parser.move_group("EmptyGroup", 0);
+        parser.prune_empty_groups();
+        assert_eq!(parser.groups().len(), 2);
+        assert!(parser.undo());
+        assert!(parser.undo());
+        assert!(parser.undo());
+        assert!(!parser.undo());
+        assert_eq!(parser.groups(), &original);
+    }
+
+    #[test]
+    fn undo_sort_by_priority_test() {
+        let mut parser = TagParser::from("[A]\nred\n[B]\n# priority: 1\nblue");
+        parser.record_history(true);
+        parser.add_tag("A", "green");
+        parser.sort_by_priority();
+        assert_eq!(parser.groups()[0].name, "B");
+
+        assert!(parser.undo());
+        assert_eq!(parser.groups()[0].name, "A");
+        assert!(parser.undo());
+        assert_eq!(parser.groups()[0].tags, vec!["red"]);
+        assert!(!parser.undo());
+    }
+
+    #[test]
+    fn undo_after_unrecorded_changes_test() {
+        let data = "[A]\nred\nblue";
+        let mut parser = TagParser::from(data);
+        parser.record_history(true);
+        parser.add_tag("A", "green");
+        for group in parser.iter_mut() {
+            group.tags.clear();
+        }
+        assert!(!parser.undo());
+        assert!(parser.groups()[0].tags.is_empty());
+
+        let mut parser = TagParser::from(data);
+        parser.record_history(true);
+        parser.add_tag("A", "green");
+        parser.parse_additional("[B]\nyellow");
+        assert!(!parser.undo());
+        assert_eq!(parser.groups()[0].tags, vec!["red", "blue", "green"]);
+
+        let mut parser = TagParser::from(data);
+        parser.record_history(true);
+        parser.add_tag("A", "green");
+        parser.rebuild_data();
+        assert!(!parser.undo());
+        assert_eq!(parser.groups()[0].tags.len(), 3);
+    }
+
+    #[test]
+    fn undo_stale_change_test() {
+        let mut parser = TagParser::from("[A]\nred");
+        parser.record_history(true);
+        parser.add_tag("A", "blue");
+        parser.record(Change::AddTag { group: 0, index: 5 });
+        parser.record(Change::MoveGroup { from: 0, to: 3 });
+
+        assert!(!parser.undo());
+        assert!(!parser.undo());
+        assert_eq!(parser.groups()[0].tags, vec!["red", "blue"]);
+
+        for change in [
+            Change::AddGroup { index: 1 },
+            Change::RemoveTag {
+                group: 2,
+                index: 0,
+                tag: "x".to_string(),
+            },
+            Change::RemoveGroups {
+                groups: vec![(4, Group::default())],
+            },
+            Change::ReplaceTags {
+                tags: vec![(1, Vec::new())],
+            },
+            Change::Reorder { order: vec![0, 1] },
+            Change::Reorder { order: vec![1] },
+        ] {
+            parser.record(change);
+            assert!(!parser.undo());
+        }
+        assert_eq!(parser.groups().len(), 1);
+    }
+}
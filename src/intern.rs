@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::{Group, TagParser};
+
+/// A pool of shared strings, so identical tags from any number of parsers share one allocation.
+///
+/// The interner can be shared between threads.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use tag_parser::Interner;
+///
+/// let interner = Interner::new();
+/// let a = interner.intern("red_hair");
+/// let b = interner.intern("red_hair");
+///
+/// assert!(Arc::ptr_eq(&a, &b));
+/// assert_eq!(interner.len(), 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct Interner {
+    /// The strings that have been interned so far.
+    strings: Mutex<HashSet<Arc<str>>>,
+}
+
+impl Interner {
+    /// Creates a new, empty `Interner`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared copy of `value`, adding it to the pool if it is not there yet.
+    pub fn intern(&self, value: &str) -> Arc<str> {
+        let mut strings = self.strings.lock().unwrap();
+        if let Some(existing) = strings.get(value) {
+            return Arc::clone(existing);
+        }
+
+        let value: Arc<str> = Arc::from(value);
+        strings.insert(Arc::clone(&value));
+        value
+    }
+
+    /// Returns the shared copy of `value` if it has been interned.
+    pub fn get(&self, value: &str) -> Option<Arc<str>> {
+        self.strings.lock().unwrap().get(value).cloned()
+    }
+
+    /// Returns the number of distinct strings in the pool.
+    pub fn len(&self) -> usize {
+        self.strings.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no strings have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A group whose name, aliases and tags are shared through an [`Interner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternedGroup {
+    /// The name of the group.
+    pub name: Arc<str>,
+    /// Other names the group can be looked up by.
+    pub aliases: Vec<Arc<str>>,
+    /// A list of tags that belong to the group.
+    pub tags: Vec<Arc<str>>,
+}
+
+impl InternedGroup {
+    /// Creates an `InternedGroup` from a group, interning its name, aliases and tags.
+    pub fn new(group: &Group, interner: &Interner) -> Self {
+        Self {
+            name: interner.intern(&group.name),
+            aliases: group
+                .aliases
+                .iter()
+                .map(|alias| interner.intern(alias))
+                .collect(),
+            tags: group.tags.iter().map(|tag| interner.intern(tag)).collect(),
+        }
+    }
+
+    /// Returns `true` if `name` is the name of the group or one of its aliases.
+    pub fn has_name(&self, name: &str) -> bool {
+        &*self.name == name || self.aliases.iter().any(|alias| &**alias == name)
+    }
+
+    /// Returns `true` if the group contains `tag`.
+    pub fn contains(&self, tag: &str) -> bool {
+        self.tags.iter().any(|existing| &**existing == tag)
+    }
+}
+
+/// The groups of a parser, with their strings stored in an [`Interner`], as returned by
+/// [`TagParser::with_interner`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InternedGroups {
+    /// The groups, in the order they were parsed.
+    groups: Vec<InternedGroup>,
+}
+
+impl InternedGroups {
+    /// Returns the groups as a slice.
+    pub fn as_slice(&self) -> &[InternedGroup] {
+        &self.groups
+    }
+
+    /// Returns the first group whose name or one of its aliases is `name`, like
+    /// [`TagParser::get_group`].
+    pub fn get_group(&self, name: &str) -> Option<&InternedGroup> {
+        self.groups.iter().find(|group| group.has_name(name))
+    }
+}
+
+impl IntoIterator for InternedGroups {
+    type Item = InternedGroup;
+    type IntoIter = std::vec::IntoIter<InternedGroup>;
+
+    /// Consumes the collection and yields its groups in order.
+    fn into_iter(self) -> Self::IntoIter {
+        self.groups.into_iter()
+    }
+}
+
+impl TagParser {
+    /// Turns the parser into its groups, with their names, aliases and tags stored in
+    /// `interner`.
+    ///
+    /// Identical strings across groups, and across parsers using the same interner, share a
+    /// single allocation. The parser is consumed, and each of its strings is freed as soon as it
+    /// has been interned, so only the shared copies remain. Groups can still be looked up by
+    /// name or alias with [`InternedGroups::get_group`]. Spans and the other group fields are
+    /// not kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `interner`: The pool that holds the shared strings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::{Interner, TagParser};
+    /// let interner = Interner::new();
+    /// let parser = TagParser::from("[Generic]\nred_hair\n[Other]\nred_hair");
+    /// let groups = parser.with_interner(&interner);
+    /// assert!(groups.get_group("Other").unwrap().contains("red_hair"));
+    /// assert_eq!(interner.len(), 3);
+    /// ```
+    pub fn with_interner(self, interner: &Interner) -> InternedGroups {
+        InternedGroups {
+            groups: self
+                .groups
+                .into_iter()
+                .map(|group| InternedGroup::new(&group, interner))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interner_test() {
+        let interner = Interner::new();
+        let first = TagParser::from("[Generic]\nred_hair\nsmile\n[Other]\nsmile");
+        let second = TagParser::from("[Generic]\nsmile");
+
+        let first_groups = first.with_interner(&interner);
+        let second_groups = second.with_interner(&interner);
+        let first_groups = first_groups.as_slice();
+        let second_groups = second_groups.as_slice();
+
+        assert!(Arc::ptr_eq(
+            &first_groups[0].tags[1],
+            &first_groups[1].tags[0]
+        ));
+        assert!(Arc::ptr_eq(
+            &first_groups[0].tags[1],
+            &second_groups[0].tags[0]
+        ));
+        assert!(Arc::ptr_eq(&first_groups[0].name, &second_groups[0].name));
+        assert!(!Arc::ptr_eq(
+            &first_groups[0].tags[0],
+            &first_groups[0].tags[1]
+        ));
+
+        assert_eq!(interner.len(), 4);
+        assert!(interner.get("smile").is_some());
+        assert!(interner.get("missing").is_none());
+        assert!(second_groups[0].contains("smile"));
+        assert!(!second_groups[0].contains("red_hair"));
+    }
+
+    #[test]
+    fn interned_lookup_test() {
+        let interner = Interner::new();
+        let parser = TagParser::from("[Generic | Common]\nred_hair\n[IDs]\n102349");
+        let groups = parser.with_interner(&interner);
+
+        let generic = groups.get_group("Generic").unwrap();
+        assert_eq!(groups.get_group("Common"), Some(generic));
+        assert_eq!(&*generic.aliases[0], "Common");
+        assert!(generic.contains("red_hair"));
+        assert!(groups.get_group("IDs").unwrap().contains("102349"));
+        assert!(groups.get_group("common").is_none());
+        assert!(groups.get_group("Missing").is_none());
+        assert_eq!(groups.into_iter().count(), 2);
+    }
+}
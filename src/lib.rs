@@ -1,5 +1,25 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::num::ParseIntError;
+use std::ops::Range;
 use std::path::Path;
 
+use history::Change;
+
+mod binary;
+mod history;
+mod intern;
+#[cfg(feature = "mmap")]
+mod mmap;
+
+pub use intern::{InternedGroup, InternedGroups, Interner};
+#[cfg(feature = "mmap")]
+pub use mmap::{MappedGroup, MappedTagParser};
+
 /// A group is a collection of tags that are related to each other.
 ///
 /// # Example
@@ -10,6 +30,7 @@ use std::path::Path;
 /// let group = Group {
 ///    name: "Generic".to_string(),
 ///    tags: vec!["red".to_string(), "hair".to_string()],
+///    ..Default::default()
 /// };
 ///
 /// assert_eq!(group.name, "Generic");
@@ -17,235 +38,6711 @@ use std::path::Path;
 /// assert_eq!(group.tags[0], "red");
 /// assert_eq!(group.tags[1], "hair");
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Group {
     /// The name of the group.
     pub name: String,
     /// A list of tags that belong to the group.
     pub tags: Vec<String>,
+    /// Other names the group can be looked up by, declared as `[Name|Alias1|Alias2]`.
+    pub aliases: Vec<String>,
+    /// The 1-based line ranges of the source data the group was parsed from.
+    ///
+    /// Each range runs from a header line to the group's last tag line. A group has one range
+    /// per header that contributed to it, and none if it was not parsed from data.
+    pub spans: Vec<Range<usize>>,
+    /// A description of the group, taken from the first comment right after its header that
+    /// is not a priority.
+    pub description: Option<String>,
+    /// The priority of the group, taken from a `# priority: 2` comment right after its header.
+    ///
+    /// Used by [`TagParser::sort_by_priority`].
+    pub priority: Option<i32>,
+    /// The verbatim source of the group, from its header to its last tag line.
+    ///
+    /// This is only recorded with [`ParseOptions::raw_blocks`]. When a [`TagParser`] is
+    /// formatted, a group that still matches its source under the parser's options is written
+    /// as this text, so untouched groups are written back exactly. Formatting the group on its
+    /// own ignores the raw block.
+    pub raw_block: Option<String>,
+    /// The tags without the negative prefix, when [`ParseOptions::negative_prefix`] is set.
+    ///
+    /// Like `negative_tags`, this is filled in while parsing and is not updated when `tags`
+    /// changes.
+    pub positive_tags: Vec<String>,
+    /// The tags that start with the negative prefix, with the prefix removed, when
+    /// [`ParseOptions::negative_prefix`] is set.
+    pub negative_tags: Vec<String>,
+    /// How deeply the group is nested, from the number of brackets around its header.
+    ///
+    /// `[Top]` has a depth of 1 and `[[Sub]]` a depth of 2 when
+    /// [`ParseOptions::nested_groups`] is on. Otherwise every parsed group has a depth of 1.
+    /// Groups that were not parsed have a depth of 0 and are written like a depth of 1.
+    pub depth: usize,
 }
 
-/// A parser that reads a file and extracts groups of tags.
-///
-/// The file should be in the following format:
-/// ```text
-/// [Group1]
-/// tag1 tag2 tag3
-/// tag4 tag5
-///
-/// [Group2]
-/// tag6 tag7
-/// ```
-///
-/// The parser will extract the groups and tags from the file.
-///
-/// # Example
-///
-/// ```no_run
-/// use tag_parser::TagParser;
-/// use std::path::Path;
-/// let path = Path::new("tags.txt");
-/// let mut parser = TagParser::new(path);
-/// parser.parse();
-/// let groups = parser.groups();
-/// ```
-///
-/// The `groups` variable will contain a list of groups with their respective tags.
-///
-/// The `parse` method must be called before accessing the groups.
-///
-/// The `TagParser` struct also implements the `From<&str>` and `From<String>` traits.
-///
-/// # Example
-///
-/// ```
-/// use tag_parser::TagParser;
-/// let data = include_str!("test_data.txt");
-/// let parser = TagParser::from(data);
-/// let groups = parser.groups();
-/// ```
-#[derive(Debug)]
-pub struct TagParser {
-    /// The data read from the file.
-    data: String,
-    /// A list of groups extracted from the data.
-    groups: Vec<Group>,
-}
-
-impl TagParser {
-    /// Creates a new `TagParser` instance from a file.
+impl Group {
+    /// Parses every tag of the group as an integer.
+    ///
+    /// This is meant for groups that hold numeric values, such as IDs.
     ///
     /// # Arguments
     ///
-    /// * `path`: A path to the file containing the tags.
+    /// * `skip_invalid`: If `true`, tags that are not integers are skipped. Otherwise the first
+    ///   such tag returns an error.
     ///
-    /// returns: A new `TagParser` instance.
+    /// returns: The parsed integers, in tag order.
     ///
-    /// # Examples
+    /// # Example
     ///
-    /// ```no_run
-    /// use tag_parser::TagParser;
-    /// use std::path::Path;
+    /// ```
+    /// use tag_parser::Group;
     ///
-    /// let path = Path::new("tags.txt");
-    /// let parser = TagParser::new(path);
+    /// let group = Group {
+    ///     name: "IDs".to_string(),
+    ///     tags: vec!["102349".to_string(), "red".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(group.numeric_tags(true), Ok(vec![102349]));
+    /// assert!(group.numeric_tags(false).is_err());
     /// ```
-    pub fn new(path: &Path) -> Self {
-        let data = std::fs::read_to_string(path).unwrap();
-        Self {
-            data,
-            groups: Vec::new(),
+    pub fn numeric_tags(&self, skip_invalid: bool) -> Result<Vec<i64>, ParseIntError> {
+        let mut numbers = Vec::new();
+        for tag in &self.tags {
+            match tag.parse::<i64>() {
+                Ok(number) => numbers.push(number),
+                Err(_) if skip_invalid => continue,
+                Err(err) => return Err(err),
+            }
         }
-    }
 
-    /// Returns a reference to the list of groups.
-    pub fn groups(&self) -> &Vec<Group> {
-        &self.groups
+        Ok(numbers)
     }
 
-    /// Parses the data and extracts the groups and tags.
+    /// Returns every tag with its weight, using the `(tag:weight)` syntax.
     ///
-    /// The groups and tags are stored in the `groups` field.
+    /// Plain tags have a weight of `1.0`. Tags that look weighted but whose weight is not a valid
+    /// number are returned unchanged with a weight of `1.0`. The tags of the group are not
+    /// modified.
     ///
     /// # Example
     ///
     /// ```
-    /// use tag_parser::TagParser;
-    /// let data = include_str!("test_data.txt");
-    /// let mut parser = TagParser::from(data);
-    /// parser.parse();
-    /// let groups = parser.groups();
+    /// use tag_parser::Group;
+    ///
+    /// let group = Group {
+    ///     name: "Prompt".to_string(),
+    ///     tags: vec!["(red_hair:1.2)".to_string(), "smile".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(
+    ///     group.weighted_tags(),
+    ///     vec![("red_hair".to_string(), 1.2), ("smile".to_string(), 1.0)]
+    /// );
     /// ```
-    pub fn parse(&mut self) {
-        let mut group = Group {
-            name: String::new(),
-            tags: Vec::new(),
-        };
+    pub fn weighted_tags(&self) -> Vec<(String, f32)> {
+        self.tags
+            .iter()
+            .map(|tag| {
+                let weighted = tag
+                    .strip_prefix('(')
+                    .and_then(|tag| tag.strip_suffix(')'))
+                    .and_then(|tag| tag.rsplit_once(':'))
+                    .and_then(|(name, weight)| {
+                        let weight = weight.trim().parse::<f32>().ok()?;
+                        let name = name.trim();
+                        (!name.is_empty()).then(|| (name.to_string(), weight))
+                    });
 
-        for line in self
-            .data
-            .lines()
-            .filter(|line| !line.is_empty() && !line.starts_with('#'))
-        {
-            if line.starts_with('[') {
-                if !group.name.is_empty() {
-                    self.groups.push(group);
-                    group = Group {
-                        name: String::new(),
-                        tags: Vec::new(),
-                    };
-                }
+                weighted.unwrap_or_else(|| (tag.clone(), 1.0))
+            })
+            .collect()
+    }
 
-                let line = line.split('#').next().unwrap().trim();
-                group.name = line[1..line.len() - 1].trim().to_string();
-            } else if !line.starts_with('[') && group.name.is_empty() {
-                continue; // Skip orphan tags
-            } else {
-                group
-                    .tags
-                    .push(line.split('#').next().unwrap().trim().to_string());
-            }
-        }
+    /// Returns the names of the tags whose `tag:confidence` score meets `threshold`.
+    ///
+    /// Names are returned without the score, in the order of the tags. Tags without a score, or
+    /// whose score is not a finite number, have a confidence of `1.0` and are returned whole.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::Group;
+    ///
+    /// let group = Group {
+    ///     name: "Predicted".to_string(),
+    ///     tags: vec!["red_hair:0.98".to_string(), "hat:0.3".to_string(), "smile".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(group.filter_by_confidence(0.5), vec!["red_hair", "smile"]);
+    /// ```
+    pub fn filter_by_confidence(&self, threshold: f32) -> Vec<&str> {
+        self.tags
+            .iter()
+            .filter_map(|tag| {
+                let scored = tag.rsplit_once(':').and_then(|(name, confidence)| {
+                    let confidence = confidence.trim().parse::<f32>().ok()?;
+                    let name = name.trim();
+                    (confidence.is_finite() && !name.is_empty()).then_some((name, confidence))
+                });
 
-        self.groups.push(group);
+                let (name, confidence) = scored.unwrap_or((tag.as_str(), 1.0));
+                (confidence >= threshold).then_some(name)
+            })
+            .collect()
     }
-}
 
-impl From<&str> for TagParser {
-    /// Creates a new `TagParser` instance from a string.
+    /// Returns the minimum, maximum and mean length of the group's tags, in characters.
     ///
-    /// # Arguments
+    /// A group without tags returns `(0, 0, 0.0)`.
     ///
-    /// * `data`: A string containing the tags.
+    /// # Example
     ///
-    /// returns: A new `TagParser` instance.
+    /// ```
+    /// use tag_parser::Group;
     ///
-    /// # Examples
+    /// let group = Group {
+    ///     name: "Colors".to_string(),
+    ///     tags: vec!["red".to_string(), "green".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(group.tag_len_stats(), (3, 5, 4.0));
+    /// ```
+    pub fn tag_len_stats(&self) -> (usize, usize, f64) {
+        len_stats(&self.tags)
+    }
+
+    /// Returns the tags of the group as a set, for when tag order and duplicates do not matter.
+    ///
+    /// The set borrows from the group, so `tags` stays the source of truth and keeps its order
+    /// and duplicates. See [`TagParser::tag_set`] for the tags of every group.
+    ///
+    /// # Example
     ///
     /// ```
     /// use tag_parser::TagParser;
-    /// let data = include_str!("test_data.txt");
-    /// let parser = TagParser::from(data);
-    /// let groups = parser.groups();
+    /// let parser = TagParser::from("[Colors]\nred\nblue\nred");
+    /// let tags = parser.groups()[0].tag_set();
+    /// assert_eq!(tags.len(), 2);
+    /// assert!(tags.contains("red"));
     /// ```
-    fn from(data: &str) -> Self {
-        let mut parser = Self {
-            data: data.to_string(),
-            groups: Vec::new(),
+    pub fn tag_set(&self) -> HashSet<&str> {
+        self.tags.iter().map(String::as_str).collect()
+    }
+
+    /// Returns the longest leading text that every tag in the group shares.
+    ///
+    /// The prefix always ends on a character boundary. A group whose tags share no prefix, or
+    /// that has no tags, returns an empty string, and a group with one tag returns that tag.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Characters]\nchar_alice\nchar_bob");
+    /// assert_eq!(parser.groups()[0].common_prefix(), "char_");
+    /// ```
+    pub fn common_prefix(&self) -> String {
+        let Some((first, rest)) = self.tags.split_first() else {
+            return String::new();
         };
 
-        parser.parse();
-        parser
+        let mut prefix = first.as_str();
+        for tag in rest {
+            let len = prefix
+                .char_indices()
+                .zip(tag.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(prefix.len().min(tag.len()), |((index, _), _)| index);
+            prefix = &prefix[..len];
+        }
+
+        prefix.to_string()
     }
-}
 
-impl From<String> for TagParser {
-    /// Creates a new `TagParser` instance from a string.
+    /// Joins the tags of the group into one string, with `sep` between them.
     ///
-    /// # Arguments
+    /// # Example
     ///
-    /// * `data`: A string containing the tags.
+    /// ```
+    /// use tag_parser::Group;
     ///
-    /// returns: A new `TagParser` instance.
+    /// let group = Group {
+    ///     name: "Colors".to_string(),
+    ///     tags: vec!["red".to_string(), "blue".to_string()],
+    ///     ..Default::default()
+    /// };
     ///
-    /// # Examples
+    /// assert_eq!(group.joined(", "), "red, blue");
+    /// ```
+    pub fn joined(&self, sep: &str) -> String {
+        self.tags.join(sep)
+    }
+
+    /// Splits the group into groups of at most `size` tags each.
+    ///
+    /// The chunks are named after the group with a 1-based counter, like `Name (1)`. A group
+    /// without tags, or a `size` of 0, produces no chunks.
+    ///
+    /// # Example
     ///
     /// ```
-    /// use tag_parser::TagParser;
-    /// let data = include_str!("test_data.txt");
-    /// let parser = TagParser::from(data);
-    /// let groups = parser.groups();
+    /// use tag_parser::Group;
+    ///
+    /// let group = Group {
+    ///     name: "Colors".to_string(),
+    ///     tags: vec!["red".to_string(), "blue".to_string(), "green".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let chunks = group.chunks(2);
+    /// assert_eq!(chunks.len(), 2);
+    /// assert_eq!(chunks[1].name, "Colors (2)");
+    /// assert_eq!(chunks[1].tags, vec!["green"]);
     /// ```
-    fn from(data: String) -> Self {
-        Self::from(data.as_str())
+    pub fn chunks(&self, size: usize) -> Vec<Group> {
+        if size == 0 {
+            return Vec::new();
+        }
+
+        self.tags
+            .chunks(size)
+            .enumerate()
+            .map(|(i, tags)| Group {
+                name: format!("{} ({})", self.name, i + 1),
+                tags: tags.to_vec(),
+                ..Default::default()
+            })
+            .collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Sorts the tags, keeping the pinned tags at the front.
+    ///
+    /// Pinned tags that are present come first, in the order given by `pinned`. The remaining
+    /// tags follow in sorted order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::Group;
+    ///
+    /// let mut group = Group {
+    ///     name: "Generic".to_string(),
+    ///     tags: vec!["smile".to_string(), "dress".to_string(), "red_hair".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// group.sort_with_pins(&["red_hair"]);
+    /// assert_eq!(group.tags, vec!["red_hair", "dress", "smile"]);
+    /// ```
+    pub fn sort_with_pins(&mut self, pinned: &[&str]) {
+        self.tags.sort_by_cached_key(|tag| {
+            let pin = pinned.iter().position(|pin| pin == tag);
+            (pin.unwrap_or(pinned.len()), tag.clone())
+        });
+    }
 
-    #[test]
-    fn group_test() {
-        let data = include_str!("test_data.txt");
-        let parser = TagParser::from(data);
-        let groups = parser.groups();
-        assert_eq!(groups.len(), 3);
-        assert_eq!(groups[0].name, "Generic");
-        assert_eq!(groups[0].tags.len(), 3);
-        assert_eq!(groups[1].name, "IDs");
-        assert_eq!(groups[1].tags.len(), 1);
-        assert_eq!(groups[2].name, "EmptyGroup");
-        assert_eq!(groups[2].tags.len(), 0);
+    /// Groups the tags by their namespace prefix, like `char` in `char:alice`.
+    ///
+    /// Each tag is split on the first `sep` into a namespace and a value. Tags without `sep` are
+    /// listed whole under the empty namespace. Values keep the order of their tags.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::Group;
+    ///
+    /// let group = Group {
+    ///     name: "Scene".to_string(),
+    ///     tags: vec!["char:alice".to_string(), "smile".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let namespaces = group.by_namespace(':');
+    /// assert_eq!(namespaces["char"], vec!["alice"]);
+    /// assert_eq!(namespaces[""], vec!["smile"]);
+    /// ```
+    pub fn by_namespace(&self, sep: char) -> HashMap<String, Vec<&str>> {
+        let mut namespaces: HashMap<String, Vec<&str>> = HashMap::new();
+        for tag in &self.tags {
+            let (namespace, value) = tag.split_once(sep).unwrap_or(("", tag));
+            namespaces
+                .entry(namespace.to_string())
+                .or_default()
+                .push(value);
+        }
+
+        namespaces
     }
 
-    #[test]
-    fn multiple_groups_test() {
-        let data = include_str!("test_data.txt");
-        let parser = TagParser::from(data);
-        let groups = parser.groups();
-        assert_eq!(groups.len(), 3);
-        assert_eq!(groups[0].name, "Generic");
-        assert_eq!(groups[0].tags.len(), 3);
-        assert_eq!(groups[1].name, "IDs");
-        assert_eq!(groups[1].tags.len(), 1);
+    /// Returns the union of the tags of two groups.
+    ///
+    /// Each tag appears once. Tags keep the order they have in `self`, followed by the tags that
+    /// only appear in `other`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::Group;
+    ///
+    /// let a = Group {
+    ///     name: "A".to_string(),
+    ///     tags: vec!["red".to_string(), "blue".to_string()],
+    ///     ..Default::default()
+    /// };
+    /// let b = Group {
+    ///     name: "B".to_string(),
+    ///     tags: vec!["blue".to_string(), "green".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(a.union(&b), vec!["red", "blue", "green"]);
+    /// ```
+    pub fn union(&self, other: &Group) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.tags
+            .iter()
+            .chain(&other.tags)
+            .filter(|tag| seen.insert(tag.as_str()))
+            .cloned()
+            .collect()
     }
 
-    #[test]
-    fn tag_test() {
-        let data = include_str!("test_data.txt");
-        let parser = TagParser::from(data);
-        let groups = parser.groups();
-        assert_eq!(groups.len(), 3);
-        assert_eq!(groups[0].name, "Generic");
-        assert_eq!(groups[0].tags.len(), 3);
+    /// Returns the Jaccard index of the tag sets of two groups.
+    ///
+    /// This is the number of distinct shared tags divided by the number of distinct tags in
+    /// either group, from `0.0` for no overlap to `1.0` for identical sets. Two groups without
+    /// tags are considered identical.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::Group;
+    ///
+    /// let a = Group {
+    ///     name: "A".to_string(),
+    ///     tags: vec!["red".to_string(), "blue".to_string()],
+    ///     ..Default::default()
+    /// };
+    /// let b = Group {
+    ///     name: "B".to_string(),
+    ///     tags: vec!["blue".to_string(), "green".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(a.similarity(&b), 1.0 / 3.0);
+    /// ```
+    pub fn similarity(&self, other: &Group) -> f64 {
+        let union = self.union(other).len();
+        if union == 0 {
+            return 1.0;
+        }
 
-        let tags = &groups[0].tags;
-        assert_eq!(tags[0].as_str(), "red_hair female dress");
-        assert_eq!(tags[1].as_str(), "dancing fire smile");
-        assert_eq!(tags[2].as_str(), "進撃の巨人");
+        self.intersection(other).len() as f64 / union as f64
+    }
 
-        let tags = &groups[1].tags;
-        assert_eq!(tags[0].as_str(), "102349");
+    /// Returns the tags that appear in both groups.
+    ///
+    /// Each tag appears once, in the order it has in `self`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::Group;
+    ///
+    /// let a = Group {
+    ///     name: "A".to_string(),
+    ///     tags: vec!["red".to_string(), "blue".to_string()],
+    ///     ..Default::default()
+    /// };
+    /// let b = Group {
+    ///     name: "B".to_string(),
+    ///     tags: vec!["blue".to_string(), "green".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(a.intersection(&b), vec!["blue"]);
+    /// ```
+    pub fn intersection(&self, other: &Group) -> Vec<String> {
+        let other_tags: HashSet<&str> = other.tags.iter().map(String::as_str).collect();
+        let mut seen = HashSet::new();
+        self.tags
+            .iter()
+            .filter(|tag| other_tags.contains(tag.as_str()) && seen.insert(tag.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
+impl fmt::Display for Group {
+    /// Formats the group as a header line, including any aliases, followed by one line per tag.
+    ///
+    /// A description and a priority are written as comments right after the header. The raw
+    /// block is not used; see [`Group::raw_block`].
+    ///
+    /// Tags that start with `[` are escaped as `\[` so they are not read back as headers. Every
+    /// line, including the last, ends with a newline.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let brackets = self.depth.max(1);
+        write!(f, "{}{}", "[".repeat(brackets), self.name)?;
+        for alias in &self.aliases {
+            write!(f, "|{alias}")?;
+        }
+        writeln!(f, "{}", "]".repeat(brackets))?;
+        if let Some(description) = &self.description {
+            writeln!(f, "# {description}")?;
+        }
+        if let Some(priority) = self.priority {
+            writeln!(f, "# priority: {priority}")?;
+        }
+        for tag in &self.tags {
+            if tag.starts_with('[') {
+                writeln!(f, "\\{tag}")?;
+            } else {
+                writeln!(f, "{tag}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decides what happens when a group name appears more than once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Every header starts a new group, even if the name was already used.
+    #[default]
+    Keep,
+    /// Tags of a repeated group are appended to the first group with that name.
+    Merge,
+}
+
+/// Decides what happens when parsing reaches a configured limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LimitPolicy {
+    /// Parsing stops quietly, keeping everything parsed before the limit.
+    #[default]
+    Stop,
+    /// Parsing stops with an error, keeping everything parsed before the limit.
+    Error,
+}
+
+/// Decides whether a line that starts with `[` is a header or a tag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeaderPolicy {
+    /// Every line that starts with `[` is a header.
+    #[default]
+    AlwaysHeader,
+    /// A bracketed line is only a header if the next line that is not a comment is a tag line.
+    RequireTagsLineBelow,
+    /// A bracketed line is a tag if a name inside the brackets contains whitespace, like
+    /// `[maybe so]`.
+    HeuristicByContent,
+}
+
+/// Decides how `[/Name]` lines that end a group are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EndMarkerPolicy {
+    /// End markers are not recognized, so `[/Name]` is a header for a group named `/Name`.
+    #[default]
+    Disabled,
+    /// An end marker ends the current group, whatever name it holds.
+    Close,
+    /// Like `Close`, but parsing stops with [`ParseError::MismatchedEndMarker`] if the marker
+    /// does not hold the name or an alias of the current group.
+    Validate,
+}
+
+/// Options that control how a `TagParser` parses its data.
+///
+/// # Example
+///
+/// ```
+/// use tag_parser::{DuplicatePolicy, ParseOptions};
+///
+/// let options = ParseOptions {
+///     duplicates: DuplicatePolicy::Merge,
+///     ..Default::default()
+/// };
+///
+/// assert_eq!(options.duplicates, DuplicatePolicy::Merge);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// How groups with the same name are handled.
+    pub duplicates: DuplicatePolicy,
+    /// Whether group names that differ only in case count as the same name when merging.
+    ///
+    /// The merged group keeps the casing of the first header. This only applies with
+    /// [`DuplicatePolicy::Merge`]. Defaults to `false`.
+    pub merge_ignore_case: bool,
+    /// Whether group names are converted to lowercase after trimming. Tags are unaffected.
+    pub lowercase_group_names: bool,
+    /// Whether a `#` in a tag line starts a comment that runs to the end of the line.
+    ///
+    /// Lines that start with `#` are always comments, and headers always allow a trailing
+    /// comment. Defaults to `true`.
+    pub trailing_comments: bool,
+    /// Characters trimmed from both ends of every tag, in addition to whitespace.
+    ///
+    /// Defaults to no extra characters, so only whitespace is trimmed.
+    pub tag_trim_chars: Vec<char>,
+    /// Enables single-line groups like `Colors: red blue green`, alongside bracket headers.
+    ///
+    /// A compact line ends the current group, so tag lines after it are orphans until the next
+    /// header. Inside a bracket group, a line is only read as a compact group when its name has
+    /// no `/` and the separator is followed by whitespace or the end of the line, so tags like
+    /// `http://example.com` stay tags. Defaults to `None`.
+    pub compact_format: Option<CompactFormat>,
+    /// A pattern that replaces the bracket syntax for headers, like `^## Group: (.+) ##$`.
+    ///
+    /// Lines matching the pattern start a new group, named after the first capture group (or
+    /// the whole match if there is none). Header lines are matched before comments, so the
+    /// pattern may start with `#`. Other lines are read as usual. Defaults to `None`.
+    #[cfg(feature = "regex")]
+    pub header_regex: Option<regex::Regex>,
+    /// The maximum number of groups to parse, or `None` for no limit.
+    ///
+    /// When a header would start a group past the limit, parsing ends as set by
+    /// `on_max_groups`. Defaults to `None`.
+    pub max_groups: Option<usize>,
+    /// What happens when `max_groups` is reached. Defaults to [`LimitPolicy::Stop`].
+    pub on_max_groups: LimitPolicy,
+    /// Whether tags must be indented under their header.
+    ///
+    /// The first tag line of a group sets the indentation. A later line with less indentation,
+    /// or with none, ends the group and is skipped as an orphan. Defaults to `false`.
+    pub indented_tags: bool,
+    /// Whether a header may leave out its closing `]`, like `[Generic`.
+    ///
+    /// When this is off, such a header stops parsing with [`ParseError::UnclosedHeader`].
+    /// Defaults to `false`.
+    pub lenient_headers: bool,
+    /// Whether each group keeps its verbatim source in [`Group::raw_block`].
+    ///
+    /// Defaults to `false`.
+    pub raw_blocks: bool,
+    /// Marks comments that start a new section, like `---` for `# --- section ---`.
+    ///
+    /// A comment whose text after the `#` starts with this pattern ends the current group, so
+    /// tag lines after it are orphans until the next header. With [`DuplicatePolicy::Merge`],
+    /// groups are only merged with groups from the same section. Defaults to `None`.
+    pub section_break: Option<String>,
+    /// How lines that start with `[` are told apart from tags. Defaults to
+    /// [`HeaderPolicy::AlwaysHeader`].
+    ///
+    /// A bracketed line that is not a header is read as a tag, brackets included.
+    pub header_policy: HeaderPolicy,
+    /// A prefix that marks negative tags, like `-` in `-blurry`.
+    ///
+    /// When set, the tags of each group are also split into [`Group::positive_tags`] and
+    /// [`Group::negative_tags`], and `tags` keeps every tag as written. Defaults to `None`.
+    pub negative_prefix: Option<String>,
+    /// How `[/Name]` end markers are handled. Defaults to [`EndMarkerPolicy::Disabled`].
+    ///
+    /// Tag lines after an end marker are orphans until the next header. A group without an end
+    /// marker still ends at the next header.
+    pub end_markers: EndMarkerPolicy,
+    /// A function applied to every line before it is parsed, like one that strips a line
+    /// number prefix.
+    ///
+    /// The function must not add line breaks, so that line numbers still match the data.
+    /// Defaults to `None`.
+    pub line_preprocessor: Option<fn(&str) -> Cow<'_, str>>,
+    /// A function applied to every group name and alias after trimming, like one that replaces
+    /// spaces with underscores.
+    ///
+    /// It runs after `lowercase_group_names`. Defaults to `None`.
+    pub group_name_transform: Option<fn(&str) -> String>,
+    /// Whether a `#!` shebang line and a front-matter block at the start of the data are
+    /// skipped.
+    ///
+    /// A front-matter block runs from a first line of `---` to the next `---` line, and may
+    /// follow a shebang. A block without a closing `---` is parsed as normal. Skipped lines are
+    /// recorded with [`SkipReason::FrontMatter`]. Defaults to `false`.
+    pub skip_front_matter: bool,
+    /// Whether the number of brackets around a header sets the depth of its group, as in
+    /// `[Top]`, `[[Sub]]` and `[[[SubSub]]]`.
+    ///
+    /// The depth is stored in [`Group::depth`], and the name is the text inside the innermost
+    /// brackets. Only the opening brackets are counted. When this is off, `[[Sub]]` is a group
+    /// named `[Sub]`. Defaults to `false`.
+    pub nested_groups: bool,
+    /// Whether a line indented with both tabs and spaces stops parsing with
+    /// [`ParseError::MixedIndentation`].
+    ///
+    /// Only the leading whitespace of a line is checked. Defaults to `false`.
+    pub reject_mixed_indentation: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            duplicates: DuplicatePolicy::default(),
+            merge_ignore_case: false,
+            lowercase_group_names: false,
+            trailing_comments: true,
+            tag_trim_chars: Vec::new(),
+            compact_format: None,
+            #[cfg(feature = "regex")]
+            header_regex: None,
+            max_groups: None,
+            on_max_groups: LimitPolicy::default(),
+            indented_tags: false,
+            lenient_headers: false,
+            raw_blocks: false,
+            section_break: None,
+            header_policy: HeaderPolicy::default(),
+            negative_prefix: None,
+            end_markers: EndMarkerPolicy::default(),
+            line_preprocessor: None,
+            group_name_transform: None,
+            skip_front_matter: false,
+            nested_groups: false,
+            reject_mixed_indentation: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Checks whether these options let [`TagParser::reparse_line`] update a single tag in
+    /// place, instead of parsing the whole data again.
+    ///
+    /// Every field is listed, so adding an option does not compile until it is sorted into
+    /// the options that are safe for an in-place update or the ones that need a full parse.
+    fn supports_incremental_reparse(&self) -> bool {
+        let ParseOptions {
+            // A tag line is still parsed the same way with these
+            merge_ignore_case: _,
+            lowercase_group_names: _,
+            trailing_comments: _,
+            tag_trim_chars: _,
+            max_groups: _,
+            on_max_groups: _,
+            lenient_headers: _,
+            raw_blocks: _,
+            group_name_transform: _,
+            nested_groups: _,
+            // These can change which group a line belongs to, or what a tag line means
+            duplicates,
+            compact_format,
+            #[cfg(feature = "regex")]
+            header_regex,
+            indented_tags,
+            section_break,
+            header_policy,
+            negative_prefix,
+            end_markers,
+            line_preprocessor,
+            skip_front_matter,
+            reject_mixed_indentation,
+        } = self;
+
+        #[cfg(feature = "regex")]
+        if header_regex.is_some() {
+            return false;
+        }
+
+        *duplicates == DuplicatePolicy::Keep
+            && compact_format.is_none()
+            && !indented_tags
+            && section_break.is_none()
+            && *header_policy == HeaderPolicy::AlwaysHeader
+            && negative_prefix.is_none()
+            && *end_markers == EndMarkerPolicy::Disabled
+            && line_preprocessor.is_none()
+            && !skip_front_matter
+            && !reject_mixed_indentation
+    }
+}
+
+/// Describes the compact `Name: tag tag tag` group format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactFormat {
+    /// The character that separates the group name from its tags. Defaults to `:`.
+    pub name_separator: char,
+    /// The characters that separate tags, or an empty list to split on any whitespace.
+    ///
+    /// Use `vec!['\t']` to split on tabs only and keep spaces within tags. Defaults to an
+    /// empty list.
+    pub tag_separators: Vec<char>,
+}
+
+impl Default for CompactFormat {
+    fn default() -> Self {
+        Self {
+            name_separator: ':',
+            tag_separators: Vec::new(),
+        }
+    }
+}
+
+/// The way groups are laid out in a tag file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatStyle {
+    /// Groups start with a `[Name]` header and list one tag per line.
+    Brackets,
+    /// Groups are single `Name: tag tag` lines.
+    Compact,
+    /// Groups start with a `[Name]` header and their tags are indented under it.
+    Indented,
+}
+
+/// A guess at the format of some tag data, as returned by [`TagParser::detect_format`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatHint {
+    /// How groups are laid out.
+    pub style: FormatStyle,
+    /// The prefix that starts comment lines, if any comments were found.
+    pub comment_prefix: Option<&'static str>,
+    /// The character that separates tags in compact lines, or `None` for whitespace.
+    pub tag_separator: Option<char>,
+}
+
+impl FormatHint {
+    /// Returns parse options that read data in the hinted format.
+    ///
+    /// Only `#` comments are understood by the parser, so other comment prefixes are ignored.
+    pub fn to_options(&self) -> ParseOptions {
+        ParseOptions {
+            compact_format: (self.style == FormatStyle::Compact).then(|| CompactFormat {
+                tag_separators: self.tag_separator.into_iter().collect(),
+                ..Default::default()
+            }),
+            indented_tags: self.style == FormatStyle::Indented,
+            ..Default::default()
+        }
+    }
+}
+
+/// A summary of the data extracted by a `TagParser`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseStats {
+    /// The number of groups.
+    pub group_count: usize,
+    /// The total number of tags across all groups.
+    pub tag_count: usize,
+    /// The average number of tags per group, or `0.0` if there are no groups.
+    pub average_tags_per_group: f64,
+    /// The number of full-line comments.
+    pub comment_count: usize,
+    /// The number of orphan tag lines that were skipped.
+    pub orphan_count: usize,
+}
+
+/// A single line of tag data, as returned by [`TagParser::events`].
+///
+/// Every event holds the 1-based line number it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A header line, with the name of the group it starts.
+    GroupStart(String, usize),
+    /// A tag line, with the tag it holds.
+    Tag(String, usize),
+    /// A full-line comment, with the text after the `#`.
+    Comment(String, usize),
+    /// An empty line.
+    Blank(usize),
+}
+
+/// The reason a line was skipped while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The line was empty.
+    Blank,
+    /// The line was a full-line comment.
+    Comment,
+    /// The line was a tag line that appeared before any header.
+    Orphan,
+    /// The line was a `[/Name]` marker that ended a group.
+    EndMarker,
+    /// The line was a shebang or part of a front-matter block at the start of the data.
+    FrontMatter,
+}
+
+/// A line that was not turned into a group or a tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedLine {
+    /// The line number, starting at 1.
+    pub line: usize,
+    /// The text of the line.
+    pub text: String,
+    /// Why the line was skipped.
+    pub reason: SkipReason,
+}
+
+/// An error that can occur while reading or parsing tag data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The data was marked as using an encoding but could not be decoded with it.
+    InvalidEncoding {
+        /// The name of the encoding.
+        encoding: &'static str,
+    },
+    /// A header is missing its closing `]` and lenient headers are off.
+    UnclosedHeader {
+        /// The 1-based line number of the header.
+        line: usize,
+    },
+    /// The data has more groups than the configured maximum.
+    TooManyGroups {
+        /// The maximum number of groups.
+        max: usize,
+    },
+    /// The bytes are not a complete binary tag file, as written by [`TagParser::to_bytes`].
+    InvalidBinary,
+    /// An end marker does not name the group it closes, and end markers are validated.
+    MismatchedEndMarker {
+        /// The 1-based line number of the end marker.
+        line: usize,
+    },
+    /// A group includes itself, directly or through other groups.
+    IncludeCycle {
+        /// The name of the group where the cycle was found.
+        group: String,
+    },
+    /// A line is indented with both tabs and spaces and mixed indentation is rejected.
+    MixedIndentation {
+        /// The 1-based line number of the line.
+        line: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidEncoding { encoding } => {
+                write!(f, "the data is not valid {encoding}")
+            }
+            ParseError::UnclosedHeader { line } => {
+                write!(f, "the header on line {line} is missing a closing `]`")
+            }
+            ParseError::TooManyGroups { max } => {
+                write!(f, "the data has more than {max} groups")
+            }
+            ParseError::MismatchedEndMarker { line } => {
+                write!(
+                    f,
+                    "the end marker on line {line} does not match the open group"
+                )
+            }
+            ParseError::InvalidBinary => write!(f, "the data is not a valid binary tag file"),
+            ParseError::IncludeCycle { group } => {
+                write!(f, "the group `{group}` includes itself")
+            }
+            ParseError::MixedIndentation { line } => {
+                write!(f, "line {line} is indented with both tabs and spaces")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A parser that reads a file and extracts groups of tags.
+///
+/// The file should be in the following format:
+/// ```text
+/// [Group1]
+/// tag1 tag2 tag3
+/// tag4 tag5
+///
+/// [Group2]
+/// tag6 tag7
+/// ```
+///
+/// A comment on the line right after a header is kept as the group's description. A
+/// `# priority: 2` comment among the comments right after a header sets the group's priority
+/// and is not used as the description.
+///
+/// A header can declare aliases for the group by separating names with `|`, like
+/// `[Generic|Common]`. The first name is the group's name.
+///
+/// A header that ends with a backslash continues on the next line, so `[Very Long \` followed
+/// by `Name]` is the header `[Very Long Name]`.
+///
+/// A tag line that should start with `[` can be escaped as `\[`; the backslash is removed from
+/// the stored tag.
+///
+/// The parser will extract the groups and tags from the file.
+///
+/// # Example
+///
+/// ```no_run
+/// use tag_parser::TagParser;
+/// use std::path::Path;
+/// let path = Path::new("tags.txt");
+/// let mut parser = TagParser::new(path);
+/// parser.parse();
+/// let groups = parser.groups();
+/// ```
+///
+/// The `groups` variable will contain a list of groups with their respective tags.
+///
+/// The `parse` method must be called before accessing the groups.
+///
+/// The `TagParser` struct also implements the `From<&str>` and `From<String>` traits.
+///
+/// # Example
+///
+/// ```
+/// use tag_parser::TagParser;
+/// let data = include_str!("test_data.txt");
+/// let parser = TagParser::from(data);
+/// let groups = parser.groups();
+/// ```
+#[derive(Debug)]
+pub struct TagParser {
+    /// The data read from the file.
+    data: String,
+    /// A list of groups extracted from the data.
+    groups: Vec<Group>,
+    /// The options that control how the data is parsed.
+    options: ParseOptions,
+    /// The lines that were skipped while parsing.
+    skipped: Vec<SkippedLine>,
+    /// The error that stopped the last parse, if any.
+    error: Option<ParseError>,
+    /// The changes that can be undone, or `None` if changes are not recorded.
+    history: Option<Vec<Change>>,
+}
+
+impl TagParser {
+    /// Creates a new `TagParser` instance from a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A path to the file containing the tags.
+    ///
+    /// returns: A new `TagParser` instance.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tag_parser::TagParser;
+    /// use std::path::Path;
+    ///
+    /// let path = Path::new("tags.txt");
+    /// let parser = TagParser::new(path);
+    /// ```
+    pub fn new(path: &Path) -> Self {
+        let data = fs::read_to_string(path).unwrap();
+        Self::unparsed(data, ParseOptions::default())
+    }
+
+    /// Creates a new `TagParser` instance from raw bytes and parses them.
+    ///
+    /// Without the `encoding` feature the bytes are read as UTF-8, and invalid sequences are
+    /// replaced with `U+FFFD`. With the `encoding` feature a UTF-8 or UTF-16 byte order mark
+    /// selects the encoding, and data without one that is not valid UTF-8 is read as Latin-1.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes`: The raw contents of a tag file.
+    ///
+    /// returns: A new `TagParser` instance, or an error if the bytes could not be decoded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from_bytes(b"[Generic]\nred_hair").unwrap();
+    /// assert_eq!(parser.groups()[0].tags[0], "red_hair");
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(Self::from(decode(bytes)?))
+    }
+
+    /// Creates a `TagParser` that holds `data` but has not parsed it yet.
+    fn unparsed(data: String, options: ParseOptions) -> Self {
+        Self {
+            data,
+            groups: Vec::new(),
+            options,
+            skipped: Vec::new(),
+            error: None,
+            history: None,
+        }
+    }
+
+    /// Creates a parser that holds `groups`, with the groups written out as its data.
+    ///
+    /// The spans are cleared, since they refer to lines of the data the groups came from.
+    fn from_groups(mut groups: Vec<Group>, options: ParseOptions) -> Self {
+        for group in &mut groups {
+            group.spans.clear();
+        }
+
+        let mut parser = Self::unparsed(String::new(), options);
+        parser.groups = groups;
+        parser.data = parser.to_string();
+        parser
+    }
+
+    /// Creates a new `TagParser` instance from every `.txt` file in a directory.
+    ///
+    /// The directory is not searched recursively. Files are parsed in sorted filename order and
+    /// their groups are combined as if by [`TagParser::parse_additional`], using
+    /// [`DuplicatePolicy::Merge`] so that a group defined in several files becomes one group.
+    /// [`TagParser::error`] returns the first error found in any of the files.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir`: A path to the directory containing the tag files.
+    ///
+    /// returns: A new `TagParser` instance, or an error if the directory or one of its files
+    /// could not be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tag_parser::TagParser;
+    /// use std::path::Path;
+    ///
+    /// let path = Path::new("tags/");
+    /// let parser = TagParser::from_dir(path).unwrap();
+    /// ```
+    pub fn from_dir(dir: &Path) -> io::Result<Self> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "txt") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let options = ParseOptions {
+            duplicates: DuplicatePolicy::Merge,
+            ..Default::default()
+        };
+        let mut parser = Self::unparsed(String::new(), options);
+
+        let mut error = None;
+        for path in paths {
+            parser.parse_additional(&fs::read_to_string(path)?);
+            error = error.or(parser.error.take());
+        }
+        parser.error = error;
+
+        Ok(parser)
+    }
+
+    /// Creates a new `TagParser` instance from a string using the given options.
+    ///
+    /// The data is parsed immediately, the same way `From<&str>` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: A string containing the tags.
+    /// * `options`: The options that control how the data is parsed.
+    ///
+    /// returns: A new `TagParser` instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tag_parser::{DuplicatePolicy, ParseOptions, TagParser};
+    /// let options = ParseOptions {
+    ///     duplicates: DuplicatePolicy::Merge,
+    ///     ..Default::default()
+    /// };
+    /// let parser = TagParser::with_options("[A]\nred\n[A]\nblue", options);
+    /// assert_eq!(parser.groups().len(), 1);
+    /// ```
+    pub fn with_options(data: &str, options: ParseOptions) -> Self {
+        let mut parser = Self::unparsed(data.to_string(), options);
+
+        parser.parse();
+        parser
+    }
+
+    /// Returns a reference to the options used when parsing.
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /// Returns a reference to the list of groups.
+    ///
+    /// Prefer [`TagParser::as_slice`], which does not expose the `Vec`.
+    pub fn groups(&self) -> &Vec<Group> {
+        &self.groups
+    }
+
+    /// Returns the groups as a slice.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\n[Shapes]\ncircle");
+    /// let names: Vec<&str> = parser.as_slice().iter().map(|group| group.name.as_str()).collect();
+    /// assert_eq!(names, vec!["Colors", "Shapes"]);
+    /// ```
+    pub fn as_slice(&self) -> &[Group] {
+        &self.groups
+    }
+
+    /// Returns an iterator that allows modifying each group in place.
+    ///
+    /// Changes made through the iterator cannot be undone, so this discards the changes recorded
+    /// for [`TagParser::undo`].
+    ///
+    /// # Example
+    /// ```
+    /// use tag_parser::TagParser;
+    ///
+    /// let mut parser = TagParser::from("[Colors]\nred\nblue");
+    /// for group in parser.iter_mut() {
+    ///     group.tags.retain(|tag| tag != "red");
+    /// }
+    /// assert_eq!(parser.groups()[0].tags, vec!["blue"]);
+    /// ```
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Group> {
+        self.clear_history();
+        self.groups.iter_mut()
+    }
+
+    /// Returns the first group whose name or one of its aliases is `name`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Generic|Common]\nred_hair");
+    /// assert_eq!(parser.get_group("Common").unwrap().name, "Generic");
+    /// assert!(parser.get_group("Missing").is_none());
+    /// ```
+    pub fn get_group(&self, name: &str) -> Option<&Group> {
+        self.group_index(name).map(|index| &self.groups[index])
+    }
+
+    /// Returns the group that the given source line belongs to.
+    ///
+    /// Header and tag lines belong to their group. Blank lines, comments and orphan tags return
+    /// `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `line`: The 1-based line number in the source data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Generic]\nred_hair\n\n[IDs]\n102349");
+    /// assert_eq!(parser.group_at_line(2).unwrap().name, "Generic");
+    /// assert!(parser.group_at_line(3).is_none());
+    /// ```
+    pub fn group_at_line(&self, line: usize) -> Option<&Group> {
+        let text = self.data.lines().nth(line.checked_sub(1)?)?;
+        if text.is_empty() || text.starts_with('#') {
+            return None;
+        }
+
+        self.groups
+            .iter()
+            .find(|group| group.spans.iter().any(|span| span.contains(&line)))
+    }
+
+    /// Returns the group whose tags are most similar to those of the named group.
+    ///
+    /// Similarity is measured with [`Group::similarity`]. The named group itself is never
+    /// returned, and ties go to the group that comes first.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name of the group to compare against, as accepted by
+    ///   [`TagParser::get_group`].
+    ///
+    /// returns: The most similar group, or `None` if the named group does not exist or is the
+    /// only group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\nblue\n[B]\nfire\n[C]\nblue");
+    /// assert_eq!(parser.most_similar_to("A").unwrap().name, "C");
+    /// ```
+    pub fn most_similar_to(&self, name: &str) -> Option<&Group> {
+        let target = self.get_group(name)?;
+        let mut best: Option<(&Group, f64)> = None;
+        for group in self
+            .groups
+            .iter()
+            .filter(|group| !std::ptr::eq(*group, target))
+        {
+            let similarity = target.similarity(group);
+            if best.is_none_or(|(_, best)| similarity > best) {
+                best = Some((group, similarity));
+            }
+        }
+
+        best.map(|(group, _)| group)
+    }
+
+    /// Returns the groups sorted by their number of tags.
+    ///
+    /// Groups with the same number of tags keep their document order.
+    ///
+    /// # Arguments
+    ///
+    /// * `descending`: If `true`, the largest groups come first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Small]\nred\n[Large]\nred\nblue");
+    /// assert_eq!(parser.groups_by_size(true)[0].name, "Large");
+    /// ```
+    pub fn groups_by_size(&self, descending: bool) -> Vec<&Group> {
+        let mut groups: Vec<&Group> = self.groups.iter().collect();
+        if descending {
+            groups.sort_by_key(|group| std::cmp::Reverse(group.tags.len()));
+        } else {
+            groups.sort_by_key(|group| group.tags.len());
+        }
+
+        groups
+    }
+
+    /// Returns each group name with up to its first `n` tags, for previews.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\nblue\ngreen\n[Empty]");
+    /// let preview = parser.preview(2);
+    /// assert_eq!(preview[0], ("Colors", &["red".to_string(), "blue".to_string()][..]));
+    /// assert!(preview[1].1.is_empty());
+    /// ```
+    pub fn preview(&self, n: usize) -> Vec<(&str, &[String])> {
+        self.groups
+            .iter()
+            .map(|group| {
+                let tags = &group.tags[..n.min(group.tags.len())];
+                (group.name.as_str(), tags)
+            })
+            .collect()
+    }
+
+    /// Splits the parser into the groups that match a predicate and the ones that do not.
+    ///
+    /// The new parsers keep the options and the group order. Their data is their groups written
+    /// in the tag file format, and the group spans are cleared.
+    ///
+    /// # Arguments
+    ///
+    /// * `f`: Returns `true` for groups that belong in the first parser.
+    ///
+    /// returns: A parser with the matching groups and a parser with the rest.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\n[Empty]\n[Shapes]\ncircle");
+    /// let (full, empty) = parser.partition(|group| !group.tags.is_empty());
+    /// assert_eq!(full.groups().len(), 2);
+    /// assert_eq!(empty.to_string(), "[Empty]\n");
+    /// ```
+    pub fn partition<F: Fn(&Group) -> bool>(self, f: F) -> (TagParser, TagParser) {
+        let (matching, rest) = self.groups.into_iter().partition(|group| f(group));
+        (
+            Self::from_groups(matching, self.options.clone()),
+            Self::from_groups(rest, self.options),
+        )
+    }
+
+    /// Splits the parser into the groups before a named group and the groups from it onward.
+    ///
+    /// Like [`TagParser::partition`], the new parsers keep the options and the group order, their
+    /// data is their groups written in the tag file format, and the group spans are cleared.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name or alias of the group the second parser starts at.
+    ///
+    /// returns: A parser with the groups before the named group and a parser starting at it. If
+    /// no group has the name, every group is in the first parser.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\n[Shapes]\ncircle\n[Sizes]\nbig");
+    /// let (head, tail) = parser.split_at_group("Shapes");
+    /// assert_eq!(head.to_string(), "[Colors]\nred\n");
+    /// assert_eq!(tail.groups().len(), 2);
+    /// ```
+    pub fn split_at_group(mut self, name: &str) -> (TagParser, TagParser) {
+        let index = self.group_index(name).unwrap_or(self.groups.len());
+        let tail = self.groups.split_off(index);
+        (
+            Self::from_groups(self.groups, self.options.clone()),
+            Self::from_groups(tail, self.options),
+        )
+    }
+
+    /// Sorts the groups by priority, lowest first.
+    ///
+    /// Groups without a priority come last. Groups with the same priority, and groups without
+    /// one, keep their order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Low]\n# priority: 5\n[None]\n[High]\n# priority: 1");
+    /// parser.sort_by_priority();
+    /// let names: Vec<&str> = parser.groups().iter().map(|group| group.name.as_str()).collect();
+    /// assert_eq!(names, vec!["High", "Low", "None"]);
+    /// ```
+    pub fn sort_by_priority(&mut self) {
+        let mut order: Vec<usize> = (0..self.groups.len()).collect();
+        order.sort_by_key(|&index| {
+            let priority = self.groups[index].priority;
+            (priority.is_none(), priority)
+        });
+        if order.iter().enumerate().all(|(to, &from)| to == from) {
+            return;
+        }
+
+        let mut groups: Vec<Option<Group>> = self.groups.drain(..).map(Some).collect();
+        self.groups = order
+            .iter()
+            .map(|&index| groups[index].take().unwrap())
+            .collect();
+        self.record(Change::Reorder { order });
+    }
+
+    /// Returns a copy of the parser with every tag passed through `f`.
+    ///
+    /// Group names, aliases and order are kept, and the original parser is left unchanged.
+    /// Like [`TagParser::partition`], the copy's data is its groups written in the tag file
+    /// format, and the group spans are cleared.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred_hair");
+    /// let spaced = parser.map_tags(|tag| tag.replace('_', " "));
+    /// assert_eq!(spaced.groups()[0].tags, vec!["red hair"]);
+    /// assert_eq!(parser.groups()[0].tags, vec!["red_hair"]);
+    /// ```
+    pub fn map_tags<F: Fn(&str) -> String>(&self, f: F) -> TagParser {
+        let groups = self
+            .groups
+            .iter()
+            .map(|group| {
+                let map = |tags: &[String]| tags.iter().map(|tag| f(tag)).collect();
+                Group {
+                    tags: map(&group.tags),
+                    positive_tags: map(&group.positive_tags),
+                    negative_tags: map(&group.negative_tags),
+                    ..group.clone()
+                }
+            })
+            .collect();
+
+        Self::from_groups(groups, self.options.clone())
+    }
+
+    /// Returns the groups whose names start with `prefix`, ignoring case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\n[Collections]\n102349\n[IDs]\n1");
+    /// let groups = parser.groups_with_prefix("co");
+    /// assert_eq!(groups.len(), 2);
+    /// ```
+    pub fn groups_with_prefix(&self, prefix: &str) -> Vec<&Group> {
+        let prefix = prefix.to_lowercase();
+        self.groups
+            .iter()
+            .filter(|group| group.name.to_lowercase().starts_with(&prefix))
+            .collect()
+    }
+
+    /// Counts the tags across all groups that satisfy a predicate.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[IDs]\n102349\nunknown\n[More]\n42");
+    /// let numeric = parser.count_tags(|tag| tag.chars().all(|c| c.is_ascii_digit()));
+    /// assert_eq!(numeric, 2);
+    /// ```
+    pub fn count_tags<F: Fn(&str) -> bool>(&self, f: F) -> usize {
+        self.groups
+            .iter()
+            .flat_map(|group| &group.tags)
+            .filter(|tag| f(tag))
+            .count()
+    }
+
+    /// Returns the minimum, maximum and mean length of all tags, in characters.
+    ///
+    /// Every tag counts once for each time it appears. Without any tags this returns
+    /// `(0, 0, 0.0)`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\n[Shapes]\ncircle\nsquare");
+    /// assert_eq!(parser.tag_len_stats(), (3, 6, 5.0));
+    /// ```
+    pub fn tag_len_stats(&self) -> (usize, usize, f64) {
+        let tags: Vec<&String> = self.groups.iter().flat_map(|group| &group.tags).collect();
+        len_stats(&tags)
+    }
+
+    /// Counts the groups that contain `tag` at least once.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\nred\n[B]\nred\n[C]\nblue");
+    /// assert_eq!(parser.tag_group_count("red"), 2);
+    /// assert_eq!(parser.count_tags(|tag| tag == "red"), 3);
+    /// ```
+    pub fn tag_group_count(&self, tag: &str) -> usize {
+        self.groups
+            .iter()
+            .filter(|group| group.tags.iter().any(|existing| existing == tag))
+            .count()
+    }
+
+    /// Returns every distinct tag across all groups.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\nblue\n[B]\nred");
+    /// let tags = parser.tag_set();
+    /// assert_eq!(tags.len(), 2);
+    /// assert!(tags.contains("blue"));
+    /// ```
+    pub fn tag_set(&self) -> HashSet<&str> {
+        self.groups
+            .iter()
+            .flat_map(|group| &group.tags)
+            .map(String::as_str)
+            .collect()
+    }
+    /// Returns every distinct tag across all groups, sorted.
+    ///
+    /// Tags are sorted by their bytes, so uppercase letters come before lowercase ones.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\nblue\n[B]\nred\ngreen");
+    /// assert_eq!(parser.vocabulary(), vec!["blue", "green", "red"]);
+    /// ```
+    pub fn vocabulary(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self.tag_set().into_iter().map(String::from).collect();
+        tags.sort_unstable();
+        tags
+    }
+
+    /// Returns the tags that every group with tags contains.
+    ///
+    /// Groups without tags are left out, so an empty group does not make the result empty. Each
+    /// tag is returned once, in the order it has in the first group with tags. If no group has
+    /// tags, nothing is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\nblue\n[B]\nblue\nred\n[C]\nred\n[Empty]");
+    /// assert_eq!(parser.common_tags(), vec!["red"]);
+    /// ```
+    pub fn common_tags(&self) -> Vec<&str> {
+        let mut populated = self.groups.iter().filter(|group| !group.tags.is_empty());
+        let Some(first) = populated.next() else {
+            return Vec::new();
+        };
+
+        let mut common: Vec<&str> = Vec::new();
+        for tag in &first.tags {
+            if !common.contains(&tag.as_str()) {
+                common.push(tag);
+            }
+        }
+        for group in populated {
+            common.retain(|tag| group.tags.iter().any(|existing| existing == tag));
+        }
+
+        common
+    }
+
+    /// Returns a URL and filename safe slug for every group name, in group order.
+    ///
+    /// Names are lowercased, every run of characters that are not ASCII letters or digits becomes
+    /// a single hyphen, and hyphens at either end are removed. Non-ASCII characters are not
+    /// transliterated, so a name with no ASCII letters or digits, like `進撃の巨人`, falls back to
+    /// `group-` followed by the 64-bit FNV-1a hash of the name as 16 hex digits. The hash does not
+    /// change between runs or versions, but different groups can share a slug.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Hair Colors!]\nred\n[進撃の巨人]\neren");
+    /// let slugs = parser.slugged_names();
+    /// assert_eq!(slugs[0], "hair-colors");
+    /// assert!(slugs[1].starts_with("group-"));
+    /// ```
+    pub fn slugged_names(&self) -> Vec<String> {
+        self.groups.iter().map(|group| slug(&group.name)).collect()
+    }
+
+    /// Returns the tags of the named group that appear in no other group.
+    ///
+    /// Each tag is returned once, in the order it has in the group. An unknown name returns no
+    /// tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name of the group, as accepted by [`TagParser::get_group`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\nblue\n[B]\nblue");
+    /// assert_eq!(parser.unique_tags_of("A"), vec!["red"]);
+    /// ```
+    pub fn unique_tags_of(&self, name: &str) -> Vec<&str> {
+        let Some(target) = self.get_group(name) else {
+            return Vec::new();
+        };
+
+        let elsewhere: HashSet<&str> = self
+            .groups
+            .iter()
+            .filter(|group| !std::ptr::eq(*group, target))
+            .flat_map(|group| &group.tags)
+            .map(String::as_str)
+            .collect();
+
+        let mut seen = HashSet::new();
+        target
+            .tags
+            .iter()
+            .map(String::as_str)
+            .filter(|tag| !elsewhere.contains(tag) && seen.insert(*tag))
+            .collect()
+    }
+
+    /// Sorts incoming tags into the groups that already contain them.
+    ///
+    /// Each tag is listed under every group that contains it, and tags that no group contains
+    /// are listed under an empty name. Tags keep their input order, and a tag given more than
+    /// once is listed once per group.
+    ///
+    /// # Arguments
+    ///
+    /// * `tags`: The tags to classify.
+    ///
+    /// returns: A map from group name to the input tags that belong to it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\n[Shapes]\ncircle");
+    /// let classified = parser.classify(&["circle", "red", "huge"]);
+    /// assert_eq!(classified["Colors"], vec!["red"]);
+    /// assert_eq!(classified["Shapes"], vec!["circle"]);
+    /// assert_eq!(classified[""], vec!["huge"]);
+    /// ```
+    pub fn classify(&self, tags: &[&str]) -> HashMap<String, Vec<String>> {
+        let mut classified: HashMap<String, Vec<String>> = HashMap::new();
+        let mut seen = HashSet::new();
+        for &tag in tags {
+            let mut names: Vec<&str> = self
+                .groups
+                .iter()
+                .filter(|group| group.tags.iter().any(|existing| existing == tag))
+                .map(|group| group.name.as_str())
+                .collect();
+            if names.is_empty() {
+                names.push("");
+            }
+
+            for name in names {
+                if seen.insert((name, tag)) {
+                    classified
+                        .entry(name.to_string())
+                        .or_default()
+                        .push(tag.to_string());
+                }
+            }
+        }
+
+        classified
+    }
+
+    /// Checks that every header line in the data has exactly one pair of brackets.
+    ///
+    /// This looks at the raw lines instead of the parsed groups, so it also finds headers that
+    /// parsing skipped or repaired. A header line is any line starting with `[` once leading
+    /// whitespace is removed; a `#` comment after the header is ignored. With
+    /// [`ParseOptions::nested_groups`], a header may have several opening brackets if it has as
+    /// many closing ones.
+    ///
+    /// returns: `Ok(())` if every header is balanced, or the 1-based line numbers of the headers
+    /// with a missing, extra or misplaced bracket.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\n[Shapes]]\ncircle\n[Sizes");
+    /// assert_eq!(parser.check_brackets(), Err(vec![3, 5]));
+    /// assert_eq!(TagParser::from("[Colors] # fine\nred").check_brackets(), Ok(()));
+    /// ```
+    pub fn check_brackets(&self) -> Result<(), Vec<usize>> {
+        let unbalanced: Vec<usize> = self
+            .data
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.split('#').next().unwrap().trim();
+                if !line.starts_with('[') {
+                    return false;
+                }
+
+                let depth = self.header_depth(line);
+                let closing = "]".repeat(depth);
+                match line[depth..].strip_suffix(closing.as_str()) {
+                    Some(names) => names.contains(['[', ']']),
+                    None => true,
+                }
+            })
+            .map(|(index, _)| index + 1)
+            .collect();
+
+        if unbalanced.is_empty() {
+            Ok(())
+        } else {
+            Err(unbalanced)
+        }
+    }
+
+    /// Maps every tag to the names of the groups that contain it.
+    ///
+    /// Group names are listed in group order, once per tag even if a group repeats the tag.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Warm]\nred\n[Flags]\nred\nblue");
+    /// let index = parser.inverted_index();
+    /// assert_eq!(index["red"], vec!["Warm", "Flags"]);
+    /// assert_eq!(index["blue"], vec!["Flags"]);
+    /// ```
+    pub fn inverted_index(&self) -> HashMap<String, Vec<String>> {
+        let mut index: HashMap<String, Vec<String>> = HashMap::new();
+        for group in &self.groups {
+            for tag in &group.tags {
+                let names = index.entry(tag.clone()).or_default();
+                if !names.contains(&group.name) {
+                    names.push(group.name.clone());
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Counts how many groups each pair of different tags appears in together.
+    ///
+    /// Each pair is keyed with the tag that sorts first on the left, so `(a, b)` and `(b, a)`
+    /// are the same pair. A tag repeated within a group is counted once for that group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\nblue\n[B]\nblue\nred\ngreen");
+    /// let counts = parser.cooccurrence();
+    /// assert_eq!(counts[&("blue".to_string(), "red".to_string())], 2);
+    /// assert_eq!(counts[&("blue".to_string(), "green".to_string())], 1);
+    /// ```
+    pub fn cooccurrence(&self) -> HashMap<(String, String), usize> {
+        let mut counts = HashMap::new();
+        for group in &self.groups {
+            let mut tags: Vec<&str> = group.tags.iter().map(String::as_str).collect();
+            tags.sort_unstable();
+            tags.dedup();
+
+            for (i, a) in tags.iter().enumerate() {
+                for b in &tags[i + 1..] {
+                    *counts.entry((a.to_string(), b.to_string())).or_insert(0) += 1;
+                }
+            }
+        }
+
+        counts
+    }
+
+    /// Returns the names of the groups whose tags are all in `have`, in group order.
+    ///
+    /// # Arguments
+    ///
+    /// * `have`: The tags that are possessed.
+    /// * `include_empty`: Whether groups without tags, which are trivially satisfied, are
+    ///   included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Warm]\nred\norange\n[Cool]\nblue\ngreen\n[Empty]");
+    /// let have = HashSet::from(["red", "orange", "blue"]);
+    /// assert_eq!(parser.satisfied_groups(&have, false), vec!["Warm"]);
+    /// assert_eq!(parser.satisfied_groups(&have, true), vec!["Warm", "Empty"]);
+    /// ```
+    pub fn satisfied_groups(&self, have: &HashSet<&str>, include_empty: bool) -> Vec<&str> {
+        self.groups
+            .iter()
+            .filter(|group| include_empty || !group.tags.is_empty())
+            .filter(|group| group.tags.iter().all(|tag| have.contains(tag.as_str())))
+            .map(|group| group.name.as_str())
+            .collect()
+    }
+
+    /// Returns the `(group, tag)` pairs whose tags are not in the allowed set, in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `allowed`: The tags that may appear in any group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\nbleu");
+    /// let allowed = HashSet::from(["red", "blue"]);
+    /// assert_eq!(
+    ///     parser.validate_against(&allowed),
+    ///     vec![("Colors".to_string(), "bleu".to_string())]
+    /// );
+    /// ```
+    pub fn validate_against(&self, allowed: &HashSet<&str>) -> Vec<(String, String)> {
+        self.groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .tags
+                    .iter()
+                    .filter(|tag| !allowed.contains(tag.as_str()))
+                    .map(|tag| (group.name.clone(), tag.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the `(group, tag)` pairs whose tags contain non-ASCII characters, in order.
+    ///
+    /// This is meant for reviewing internationalized tags, or spotting text that was decoded
+    /// with the wrong encoding; such tags are still valid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Food]\ncafé\ntea");
+    /// assert_eq!(
+    ///     parser.find_non_ascii_tags(),
+    ///     vec![("Food".to_string(), "café".to_string())]
+    /// );
+    /// ```
+    pub fn find_non_ascii_tags(&self) -> Vec<(String, String)> {
+        self.groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .tags
+                    .iter()
+                    .filter(|tag| !tag.is_ascii())
+                    .map(|tag| (group.name.clone(), tag.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the `(group, tag)` pairs in this parser that are missing from `other`.
+    ///
+    /// Groups are matched by name, and every group in `other` with that name is checked. A tag
+    /// that appears several times here is reported once.
+    ///
+    /// # Arguments
+    ///
+    /// * `other`: The parser to compare against.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let source = TagParser::from("[Colors]\nred\nblue\n[Shapes]\ncircle");
+    /// let target = TagParser::from("[Colors]\nred");
+    /// assert_eq!(
+    ///     source.tags_missing_from(&target),
+    ///     vec![
+    ///         ("Colors".to_string(), "blue".to_string()),
+    ///         ("Shapes".to_string(), "circle".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn tags_missing_from(&self, other: &TagParser) -> Vec<(String, String)> {
+        // Starts with the pairs in `other`, and gains each reported pair so it is reported once
+        let mut seen: HashSet<(&str, &str)> = other
+            .groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .tags
+                    .iter()
+                    .map(|tag| (group.name.as_str(), tag.as_str()))
+            })
+            .collect();
+
+        self.groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .tags
+                    .iter()
+                    .map(move |tag| (group.name.as_str(), tag.as_str()))
+            })
+            .filter(|pair| seen.insert(*pair))
+            .map(|(group, tag)| (group.to_string(), tag.to_string()))
+            .collect()
+    }
+
+    /// Adds the groups to a map from group name to tags.
+    ///
+    /// Tags of a group whose name is already in the map are appended to the existing entry, so
+    /// several parsers can be collected into the same map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use tag_parser::TagParser;
+    ///
+    /// let mut map = HashMap::new();
+    /// TagParser::from("[Generic]\nred_hair").collect_into(&mut map);
+    /// TagParser::from("[Generic]\nsmile").collect_into(&mut map);
+    /// assert_eq!(map["Generic"], vec!["red_hair", "smile"]);
+    /// ```
+    pub fn collect_into(&self, map: &mut HashMap<String, Vec<String>>) {
+        for group in &self.groups {
+            match map.get_mut(&group.name) {
+                Some(tags) => tags.extend_from_slice(&group.tags),
+                None => {
+                    map.insert(group.name.clone(), group.tags.clone());
+                }
+            }
+        }
+    }
+
+    /// Returns a map from group name to the group's tags joined with `sep`.
+    ///
+    /// Tags of groups that share a name are joined into the same entry, in order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Colors]\nred\nblue\n[Empty]");
+    /// let joined = parser.joined_tags(" ");
+    /// assert_eq!(joined["Colors"], "red blue");
+    /// assert_eq!(joined["Empty"], "");
+    /// ```
+    pub fn joined_tags(&self, sep: &str) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        self.collect_into(&mut map);
+        map.into_iter()
+            .map(|(name, tags)| (name, tags.join(sep)))
+            .collect()
+    }
+
+    /// Collects the tags of every group into a single group.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name of the new group.
+    /// * `dedup`: If `true`, each tag appears only once, at its first position.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\n[B]\nblue\nred");
+    /// assert_eq!(parser.flatten("All", true).tags, vec!["red", "blue"]);
+    /// ```
+    pub fn flatten(&self, name: &str, dedup: bool) -> Group {
+        let mut seen = HashSet::new();
+        let tags = self
+            .groups
+            .iter()
+            .flat_map(|group| &group.tags)
+            .filter(|tag| !dedup || seen.insert(tag.as_str()))
+            .cloned()
+            .collect();
+
+        Group {
+            name: name.to_string(),
+            tags,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if more than one group has the same name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// assert!(TagParser::from("[A]\n[B]\n[A]").has_duplicate_groups());
+    /// assert!(!TagParser::from("[A]\n[B]").has_duplicate_groups());
+    /// ```
+    pub fn has_duplicate_groups(&self) -> bool {
+        !self.duplicate_group_names().is_empty()
+    }
+
+    /// Returns the names used by more than one group.
+    ///
+    /// Each name is listed once, in the order it first appears.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\n[B]\n[A]\n[B]\n[C]");
+    /// assert_eq!(parser.duplicate_group_names(), vec!["A", "B"]);
+    /// ```
+    pub fn duplicate_group_names(&self) -> Vec<&str> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for group in &self.groups {
+            *counts.entry(group.name.as_str()).or_default() += 1;
+        }
+
+        let mut seen = HashSet::new();
+        self.groups
+            .iter()
+            .map(|group| group.name.as_str())
+            .filter(|name| counts[name] > 1 && seen.insert(*name))
+            .collect()
+    }
+
+    /// Returns the names used by more than one group where those groups have different tags.
+    ///
+    /// Tag order and repeated tags are ignored when comparing, so groups that only repeat each
+    /// other are plain duplicates and are not listed. Each name is listed once, in the order it
+    /// first appears.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\n[B]\nblue\n[A]\ngreen\n[B]\nblue");
+    /// assert_eq!(parser.conflicting_groups(), vec!["A"]);
+    /// ```
+    pub fn conflicting_groups(&self) -> Vec<&str> {
+        self.duplicate_group_names()
+            .into_iter()
+            .filter(|name| {
+                let mut tag_sets = self
+                    .groups
+                    .iter()
+                    .filter(|group| group.name == *name)
+                    .map(|group| group.tags.iter().collect::<HashSet<_>>());
+                let first = tag_sets.next().unwrap_or_default();
+                tag_sets.any(|tags| tags != first)
+            })
+            .collect()
+    }
+
+    /// Finds groups that have the same set of tags, regardless of their names.
+    ///
+    /// Tag order and repeated tags are ignored when comparing. Each returned cluster holds the
+    /// names of two or more groups, in document order, and clusters are ordered by their first
+    /// group. Groups without tags are considered identical to each other.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[A]\nred\nblue\n[B]\nfire\n[C]\nblue\nred");
+    /// assert_eq!(parser.duplicate_group_bodies(), vec![vec!["A", "C"]]);
+    /// ```
+    pub fn duplicate_group_bodies(&self) -> Vec<Vec<&str>> {
+        let mut clusters: Vec<Vec<&str>> = Vec::new();
+        let mut cluster_of: HashMap<Vec<&str>, usize> = HashMap::new();
+        for group in &self.groups {
+            let mut body: Vec<&str> = group.tags.iter().map(String::as_str).collect();
+            body.sort_unstable();
+            body.dedup();
+
+            let index = *cluster_of.entry(body).or_insert_with(|| {
+                clusters.push(Vec::new());
+                clusters.len() - 1
+            });
+            clusters[index].push(group.name.as_str());
+        }
+
+        clusters.retain(|names| names.len() > 1);
+        clusters
+    }
+
+    /// Returns the names of the groups that have no tags.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let data = include_str!("test_data.txt");
+    /// let parser = TagParser::from(data);
+    /// assert_eq!(parser.empty_groups(), vec!["EmptyGroup"]);
+    /// ```
+    pub fn empty_groups(&self) -> Vec<&str> {
+        self.groups
+            .iter()
+            .filter(|group| group.tags.is_empty())
+            .map(|group| group.name.as_str())
+            .collect()
+    }
+
+    /// Returns the names of the groups that only became empty because their lines held nothing
+    /// but comments.
+    ///
+    /// A group counts if all of its tags are empty, and it either has tags that were emptied by
+    /// stripping a trailing comment, or comment lines between its header and the next one. A
+    /// group declared with nothing but blank lines under it is not listed; see
+    /// [`TagParser::empty_groups`] for those.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Declared]\n\n[Commented]\n# red\n[Stripped]\n  # blue");
+    /// assert_eq!(parser.effectively_empty_groups(), vec!["Commented", "Stripped"]);
+    /// assert_eq!(parser.empty_groups(), vec!["Declared", "Commented"]);
+    /// ```
+    pub fn effectively_empty_groups(&self) -> Vec<&str> {
+        let mut headers: Vec<usize> = self
+            .groups
+            .iter()
+            .flat_map(|group| group.spans.iter().map(|span| span.start))
+            .collect();
+        headers.sort_unstable();
+
+        let has_comments = |group: &Group| {
+            group.spans.iter().any(|span| {
+                let next = headers.iter().find(|&&header| header > span.start);
+                self.skipped.iter().any(|skipped| {
+                    skipped.reason == SkipReason::Comment
+                        && skipped.line > span.start
+                        && next.is_none_or(|&next| skipped.line < next)
+                })
+            })
+        };
+
+        self.groups
+            .iter()
+            .filter(|group| group.tags.iter().all(String::is_empty))
+            .filter(|group| !group.tags.is_empty() || has_comments(group))
+            .map(|group| group.name.as_str())
+            .collect()
+    }
+
+    /// Removes every group that has no tags.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let data = include_str!("test_data.txt");
+    /// let mut parser = TagParser::from(data);
+    /// parser.prune_empty_groups();
+    /// assert!(parser.empty_groups().is_empty());
+    /// ```
+    pub fn prune_empty_groups(&mut self) {
+        let mut removed = Vec::new();
+        let mut index = 0;
+        self.groups.retain(|group| {
+            let keep = !group.tags.is_empty();
+            if !keep && self.history.is_some() {
+                removed.push((index, group.clone()));
+            }
+            index += 1;
+            keep
+        });
+
+        if !removed.is_empty() {
+            self.record(Change::RemoveGroups { groups: removed });
+        }
+    }
+
+    /// Removes the extra groups from every run of consecutive groups that have no tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `remove_all`: Whether the first group of each run is removed too, instead of kept.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[A]\n[B]\n[C]\nred\n[D]");
+    /// parser.collapse_empty_runs(false);
+    /// let names: Vec<&str> = parser.groups().iter().map(|group| group.name.as_str()).collect();
+    /// assert_eq!(names, vec!["A", "C", "D"]);
+    /// ```
+    pub fn collapse_empty_runs(&mut self, remove_all: bool) {
+        let mut removed = Vec::new();
+        let mut index = 0;
+        let mut previous_empty = false;
+        self.groups.retain(|group| {
+            let empty = group.tags.is_empty();
+            let keep = !empty || (!previous_empty && !remove_all);
+            if !keep && self.history.is_some() {
+                removed.push((index, group.clone()));
+            }
+            previous_empty = empty;
+            index += 1;
+            keep
+        });
+
+        if !removed.is_empty() {
+            self.record(Change::RemoveGroups { groups: removed });
+        }
+    }
+
+    /// Exports the groups as CSV, with one `group,tag` row per tag.
+    ///
+    /// Fields containing commas, quotes or line breaks are quoted, and quotes inside them are
+    /// doubled. A group without tags is written as a single row with an empty tag field, so that
+    /// it survives the export. Every row ends with a newline and there is no header row.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Generic]\nred_hair\nred, blue\n[EmptyGroup]");
+    /// assert_eq!(
+    ///     parser.to_csv(),
+    ///     "Generic,red_hair\nGeneric,\"red, blue\"\nEmptyGroup,\n"
+    /// );
+    /// ```
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for group in &self.groups {
+            let name = csv_field(&group.name);
+            if group.tags.is_empty() {
+                csv.push_str(&format!("{name},\n"));
+            }
+
+            for tag in &group.tags {
+                csv.push_str(&format!("{name},{}\n", csv_field(tag)));
+            }
+        }
+
+        csv
+    }
+
+    /// Exports the groups as Markdown, with a `## Name` heading and a bulleted tag list per group.
+    ///
+    /// A group without tags gets an italic `(no tags)` note instead of a list. Groups are
+    /// separated by blank lines and the output ends with a newline.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Generic]\nred_hair\nsmile\n[EmptyGroup]");
+    /// assert_eq!(
+    ///     parser.to_markdown(),
+    ///     "## Generic\n\n- red_hair\n- smile\n\n## EmptyGroup\n\n*(no tags)*\n"
+    /// );
+    /// ```
+    pub fn to_markdown(&self) -> String {
+        let sections: Vec<String> = self
+            .groups
+            .iter()
+            .map(|group| {
+                let mut section = format!("## {}\n\n", group.name);
+                if group.tags.is_empty() {
+                    section.push_str("*(no tags)*\n");
+                }
+
+                for tag in &group.tags {
+                    section.push_str(&format!("- {tag}\n"));
+                }
+                section
+            })
+            .collect();
+
+        sections.join("\n")
+    }
+
+    /// Exports the groups as TOML, with one table per group holding a `tags` array.
+    ///
+    /// Group names that are not valid bare keys are quoted. Every table is followed by a blank
+    /// line except the last. Groups that share a name produce duplicate tables, which TOML does
+    /// not allow, so merge them first with [`DuplicatePolicy::Merge`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Generic]\nred_hair\n[Long Name]");
+    /// assert_eq!(
+    ///     parser.to_toml(),
+    ///     "[Generic]\ntags = [\"red_hair\"]\n\n[\"Long Name\"]\ntags = []\n"
+    /// );
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> String {
+        let tables: Vec<String> = self
+            .groups
+            .iter()
+            .map(|group| {
+                let tags: Vec<String> = group.tags.iter().map(|tag| toml_string(tag)).collect();
+                format!(
+                    "[{}]\ntags = [{}]\n",
+                    toml_key(&group.name),
+                    tags.join(", ")
+                )
+            })
+            .collect();
+
+        tables.join("\n")
+    }
+
+    /// Moves the first group with the given name to a new position.
+    ///
+    /// An index past the end moves the group to the end. A group that is already at the
+    /// position is left alone, and nothing is recorded for [`TagParser::undo`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name of the group to move, as accepted by [`TagParser::get_group`].
+    /// * `to_index`: The position the group should end up at.
+    ///
+    /// returns: `true` if the group was found, `false` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[A]\n[B]\n[C]");
+    /// assert!(parser.move_group("C", 0));
+    /// assert_eq!(parser.groups()[0].name, "C");
+    /// ```
+    pub fn move_group(&mut self, name: &str, to_index: usize) -> bool {
+        let Some(from_index) = self.group_index(name) else {
+            return false;
+        };
+
+        let to_index = to_index.min(self.groups.len() - 1);
+        if from_index == to_index {
+            return true;
+        }
+
+        let group = self.groups.remove(from_index);
+        self.groups.insert(to_index, group);
+        self.record(Change::MoveGroup {
+            from: from_index,
+            to: to_index,
+        });
+        true
+    }
+
+    /// Adds a tag to the end of the first group with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `group`: The name of the group, as accepted by [`TagParser::get_group`].
+    /// * `tag`: The tag to add.
+    ///
+    /// returns: `true` if the group was found, `false` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Generic]\nred_hair");
+    /// assert!(parser.add_tag("Generic", "smile"));
+    /// assert_eq!(parser.groups()[0].tags, vec!["red_hair", "smile"]);
+    /// ```
+    pub fn add_tag(&mut self, group: &str, tag: &str) -> bool {
+        let Some(group) = self.group_index(group) else {
+            return false;
+        };
+
+        let tags = &mut self.groups[group].tags;
+        tags.push(tag.to_string());
+        let index = tags.len() - 1;
+        self.record(Change::AddTag { group, index });
+        true
+    }
+
+    /// Keeps only the first `max` tags of every group, dropping the rest.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Colors]\nred\nblue\ngreen\n[Shapes]\ncircle");
+    /// parser.truncate_groups(1);
+    /// assert_eq!(parser.groups()[0].tags, vec!["red"]);
+    /// assert_eq!(parser.groups()[1].tags, vec!["circle"]);
+    /// ```
+    pub fn truncate_groups(&mut self, max: usize) {
+        let mut old_tags = Vec::new();
+        for (index, group) in self.groups.iter_mut().enumerate() {
+            if group.tags.len() > max {
+                old_tags.push((index, group.tags.clone()));
+                group.tags.truncate(max);
+            }
+        }
+
+        if !old_tags.is_empty() {
+            self.record(Change::ReplaceTags { tags: old_tags });
+        }
+    }
+
+    /// Replaces all tags of the first group with the given name, creating the group if needed.
+    ///
+    /// A new group is added at the end.
+    ///
+    /// # Arguments
+    ///
+    /// * `group`: The name of the group, as accepted by [`TagParser::get_group`].
+    /// * `tags`: The new tags of the group.
+    ///
+    /// returns: `true` if an existing group was changed, `false` if a new group was created.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Generic]\nred_hair");
+    /// assert!(parser.set_tags("Generic", vec!["smile".to_string()]));
+    /// assert!(!parser.set_tags("Colors", vec!["red".to_string()]));
+    /// assert_eq!(parser.groups()[0].tags, vec!["smile"]);
+    /// assert_eq!(parser.groups()[1].name, "Colors");
+    /// ```
+    pub fn set_tags(&mut self, group: &str, tags: Vec<String>) -> bool {
+        let Some(index) = self.group_index(group) else {
+            self.groups.push(Group {
+                name: group.to_string(),
+                tags,
+                ..Default::default()
+            });
+            self.record(Change::AddGroup {
+                index: self.groups.len() - 1,
+            });
+            return false;
+        };
+
+        let old_tags = std::mem::replace(&mut self.groups[index].tags, tags);
+        self.record(Change::ReplaceTags {
+            tags: vec![(index, old_tags)],
+        });
+        true
+    }
+
+    /// Removes the first occurrence of a tag from the first group with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `group`: The name of the group, as accepted by [`TagParser::get_group`].
+    /// * `tag`: The tag to remove.
+    ///
+    /// returns: `true` if the tag was found and removed, `false` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Generic]\nred_hair\nsmile");
+    /// assert!(parser.remove_tag("Generic", "red_hair"));
+    /// assert_eq!(parser.groups()[0].tags, vec!["smile"]);
+    /// ```
+    pub fn remove_tag(&mut self, group: &str, tag: &str) -> bool {
+        let Some(group) = self.group_index(group) else {
+            return false;
+        };
+        let Some(index) = self.groups[group].tags.iter().position(|t| t == tag) else {
+            return false;
+        };
+
+        let tag = self.groups[group].tags.remove(index);
+        self.record(Change::RemoveTag { group, index, tag });
+        true
+    }
+
+    /// Removes the first group with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The name of the group, as accepted by [`TagParser::get_group`].
+    ///
+    /// returns: The removed group, or `None` if there was no such group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Generic]\nred_hair\n[IDs]\n102349");
+    /// assert_eq!(parser.remove_group("IDs").unwrap().tags, vec!["102349"]);
+    /// assert_eq!(parser.groups().len(), 1);
+    /// ```
+    pub fn remove_group(&mut self, name: &str) -> Option<Group> {
+        let index = self.group_index(name)?;
+        let group = self.groups.remove(index);
+        if self.history.is_some() {
+            self.record(Change::RemoveGroups {
+                groups: vec![(index, group.clone())],
+            });
+        }
+
+        Some(group)
+    }
+
+    /// Returns the index of the first group whose name or one of its aliases is `name`.
+    fn group_index(&self, name: &str) -> Option<usize> {
+        self.groups
+            .iter()
+            .position(|group| group.name == name || group.aliases.iter().any(|alias| alias == name))
+    }
+
+    /// Renames a tag in every group it appears in.
+    ///
+    /// # Arguments
+    ///
+    /// * `old`: The tag to rename.
+    /// * `new`: The new tag.
+    /// * `dedup`: If `true`, a group that ends up containing `new` more than once keeps only the
+    ///   first occurrence.
+    ///
+    /// returns: The number of occurrences that were renamed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[A]\nred\n[B]\nred\ncrimson");
+    /// assert_eq!(parser.rename_tag("red", "crimson", true), 2);
+    /// assert_eq!(parser.groups()[1].tags, vec!["crimson"]);
+    /// ```
+    pub fn rename_tag(&mut self, old: &str, new: &str, dedup: bool) -> usize {
+        if self.history.is_some() {
+            let tags = (0..self.groups.len())
+                .filter(|&i| self.groups[i].tags.iter().any(|tag| tag == old))
+                .map(|i| (i, self.groups[i].tags.clone()))
+                .collect::<Vec<_>>();
+            if !tags.is_empty() {
+                self.record(Change::ReplaceTags { tags });
+            }
+        }
+
+        let mut renamed = 0;
+        for group in &mut self.groups {
+            let mut group_renamed = false;
+            for tag in group.tags.iter_mut().filter(|tag| *tag == old) {
+                *tag = new.to_string();
+                renamed += 1;
+                group_renamed = true;
+            }
+
+            if dedup && group_renamed {
+                let mut seen = false;
+                group
+                    .tags
+                    .retain(|tag| tag != new || !std::mem::replace(&mut seen, true));
+            }
+        }
+
+        renamed
+    }
+
+    /// Adds the synonyms of every tag in a group to the end of that group.
+    ///
+    /// A synonym that the group already has is not added again. Synonyms are not expanded
+    /// further, so a synonym of a synonym is only added if its own base tag is in the group.
+    ///
+    /// # Arguments
+    ///
+    /// * `map`: A map from a base tag to the tags it implies.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use tag_parser::TagParser;
+    ///
+    /// let mut parser = TagParser::from("[Colors]\nred\nblue");
+    /// let map = HashMap::from([("red".to_string(), vec!["crimson".to_string()])]);
+    /// parser.expand_synonyms(&map);
+    /// assert_eq!(parser.groups()[0].tags, vec!["red", "blue", "crimson"]);
+    /// ```
+    pub fn expand_synonyms(&mut self, map: &HashMap<String, Vec<String>>) {
+        let mut old_tags = Vec::new();
+        for (index, group) in self.groups.iter_mut().enumerate() {
+            let mut seen: HashSet<String> = group.tags.iter().cloned().collect();
+            let synonyms: Vec<String> = group
+                .tags
+                .iter()
+                .filter_map(|tag| map.get(tag))
+                .flatten()
+                .filter(|synonym| seen.insert(synonym.to_string()))
+                .cloned()
+                .collect();
+
+            if !synonyms.is_empty() {
+                old_tags.push((index, group.tags.clone()));
+                group.tags.extend(synonyms);
+            }
+        }
+
+        if !old_tags.is_empty() {
+            self.record(Change::ReplaceTags { tags: old_tags });
+        }
+    }
+
+    /// Replaces every `@Name` tag with the tags of the group it refers to.
+    ///
+    /// References are looked up by group name or alias, and included groups can hold
+    /// references of their own. A tag like `@name` that does not match any group is kept as
+    /// it is. If any group includes itself, nothing is changed.
+    ///
+    /// returns: `Ok(())` if every reference was resolved, or [`ParseError::IncludeCycle`] when a
+    /// group includes itself.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Base]\nred\n[Extended]\n@Base\nblue");
+    /// parser.resolve_includes().unwrap();
+    /// assert_eq!(parser.groups()[1].tags, vec!["red", "blue"]);
+    /// ```
+    pub fn resolve_includes(&mut self) -> Result<(), ParseError> {
+        let mut resolved = HashMap::new();
+        for index in 0..self.groups.len() {
+            self.resolve_group_includes(index, &mut Vec::new(), &mut resolved)?;
+        }
+
+        let tags: Vec<(usize, Vec<String>)> = resolved
+            .into_iter()
+            .filter(|(index, tags)| self.groups[*index].tags != *tags)
+            .collect();
+        if tags.is_empty() {
+            return Ok(());
+        }
+
+        if self.history.is_some() {
+            let old_tags = tags
+                .iter()
+                .map(|(index, _)| (*index, self.groups[*index].tags.clone()))
+                .collect();
+            self.record(Change::ReplaceTags { tags: old_tags });
+        }
+        for (index, tags) in tags {
+            self.groups[index].tags = tags;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the tags of the group at `index` with its references resolved.
+    ///
+    /// `visiting` holds the groups whose references are being resolved, to detect cycles, and
+    /// `resolved` caches the groups that are done.
+    fn resolve_group_includes(
+        &self,
+        index: usize,
+        visiting: &mut Vec<usize>,
+        resolved: &mut HashMap<usize, Vec<String>>,
+    ) -> Result<Vec<String>, ParseError> {
+        if let Some(tags) = resolved.get(&index) {
+            return Ok(tags.clone());
+        }
+        if visiting.contains(&index) {
+            return Err(ParseError::IncludeCycle {
+                group: self.groups[index].name.clone(),
+            });
+        }
+
+        visiting.push(index);
+        let mut tags = Vec::new();
+        for tag in &self.groups[index].tags {
+            match tag
+                .strip_prefix('@')
+                .and_then(|name| self.group_index(name))
+            {
+                Some(included) => {
+                    tags.extend(self.resolve_group_includes(included, visiting, resolved)?)
+                }
+                None => tags.push(tag.clone()),
+            }
+        }
+        visiting.pop();
+
+        resolved.insert(index, tags.clone());
+        Ok(tags)
+    }
+
+    /// Returns the 1-based line numbers of tag lines that end with whitespace.
+    ///
+    /// This looks at the raw data, before tags are trimmed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Generic]\nred_hair \nsmile");
+    /// assert_eq!(parser.lint_whitespace(), vec![2]);
+    /// ```
+    pub fn lint_whitespace(&self) -> Vec<usize> {
+        (1..)
+            .zip(self.data.lines())
+            .filter(|(_, line)| is_tag_line(line) && line.ends_with(char::is_whitespace))
+            .map(|(number, _)| number)
+            .collect()
+    }
+
+    /// Returns a summary of the parsed data.
+    ///
+    /// The comment and orphan counts cover every parse since the last call to
+    /// [`TagParser::parse`], including [`TagParser::parse_additional`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let data = include_str!("test_data.txt");
+    /// let parser = TagParser::from(data);
+    /// let stats = parser.stats();
+    /// assert_eq!(stats.group_count, 3);
+    /// assert_eq!(stats.tag_count, 4);
+    /// ```
+    pub fn stats(&self) -> ParseStats {
+        let group_count = self.groups.len();
+        let tag_count = self.groups.iter().map(|group| group.tags.len()).sum();
+        let average_tags_per_group = if group_count == 0 {
+            0.0
+        } else {
+            tag_count as f64 / group_count as f64
+        };
+
+        ParseStats {
+            group_count,
+            tag_count,
+            average_tags_per_group,
+            comment_count: self.count_skipped(SkipReason::Comment),
+            orphan_count: self.count_skipped(SkipReason::Orphan),
+        }
+    }
+
+    /// Returns the lines that were not turned into a group or a tag, with the reason why.
+    ///
+    /// Like the stats, this covers every parse since the last call to [`TagParser::parse`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::{SkipReason, TagParser};
+    /// let parser = TagParser::from("orphan\n[Generic]\n# comment\nred_hair");
+    /// let skipped = parser.skipped();
+    /// assert_eq!(skipped.len(), 2);
+    /// assert_eq!(skipped[0].line, 1);
+    /// assert_eq!(skipped[0].reason, SkipReason::Orphan);
+    /// assert_eq!(skipped[1].reason, SkipReason::Comment);
+    /// ```
+    pub fn skipped(&self) -> &Vec<SkippedLine> {
+        &self.skipped
+    }
+
+    /// Returns `true` if the last parse had no error and no orphan lines.
+    ///
+    /// Blank and comment lines are expected in any document, so they do not count.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// assert!(TagParser::from("# Colors\n[Colors]\nred\n\n").is_clean());
+    /// assert!(!TagParser::from("stray\n[Colors]\nred").is_clean());
+    /// ```
+    pub fn is_clean(&self) -> bool {
+        self.error.is_none() && self.count_skipped(SkipReason::Orphan) == 0
+    }
+
+    /// Counts the skipped lines with the given reason.
+    fn count_skipped(&self, reason: SkipReason) -> usize {
+        self.skipped
+            .iter()
+            .filter(|skipped| skipped.reason == reason)
+            .count()
+    }
+
+    /// Writes the groups to a file in the same format as `Display`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A path to the file to write. An existing file is overwritten.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use tag_parser::TagParser;
+    /// use std::path::Path;
+    ///
+    /// let parser = TagParser::from("[Generic]\nred_hair");
+    /// parser.write_to_file(Path::new("tags.txt")).unwrap();
+    /// ```
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Writes the groups to a file, keeping the comments and layout of the data outside the
+    /// groups that were changed.
+    ///
+    /// Lines of the data that are not part of any group, like comments and blank lines between
+    /// groups, are written as they are and stay next to the groups around them. A group that
+    /// still parses back into itself, with the parser's options, is written as its original
+    /// lines, the same way a [`Group::raw_block`] is, so comments inside it survive too. A
+    /// changed group is written in the same format as `Display` in place of its original lines.
+    /// A group without a span, like one added with [`TagParser::set_tags`], or one moved out of
+    /// its original order, like with [`TagParser::move_group`], is written where it comes in the
+    /// group order without the lines that were around it. The lines of removed groups are
+    /// dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A path to the file to write. An existing file is overwritten.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use tag_parser::TagParser;
+    /// use std::path::Path;
+    ///
+    /// let mut parser = TagParser::from("# Colors I like\n[Colors]\nred\n\n# Shapes\n[Shapes]\ncircle");
+    /// parser.add_tag("Shapes", "square");
+    /// parser.write_preserving_comments(Path::new("tags.txt")).unwrap();
+    /// ```
+    pub fn write_preserving_comments(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string_preserving_comments())
+    }
+
+    /// Formats the groups for [`TagParser::write_preserving_comments`].
+    fn to_string_preserving_comments(&self) -> String {
+        let lines: Vec<&str> = self.data.lines().collect();
+
+        // Lines that belonged to a group when the data was parsed are only written by that group
+        let mut in_group = vec![false; lines.len()];
+        let original = TagParser::with_options(&self.data, self.options.clone());
+        for span in original.groups.iter().flat_map(|group| &group.spans) {
+            for line in span.clone() {
+                in_group[line - 1] = true;
+            }
+        }
+
+        // The longest run of groups that are still in their original order stays in place, and
+        // the other groups have moved
+        let spans: Vec<Option<&Range<usize>>> = self
+            .groups
+            .iter()
+            .map(|group| {
+                group
+                    .spans
+                    .first()
+                    .filter(|span| span.end <= lines.len() + 1)
+            })
+            .collect();
+        let starts: Vec<(usize, usize)> = spans
+            .iter()
+            .enumerate()
+            .filter_map(|(index, span)| span.map(|span| (index, span.start)))
+            .collect();
+        let mut tails: Vec<usize> = Vec::new();
+        let mut previous = vec![None; starts.len()];
+        for (position, &(_, start)) in starts.iter().enumerate() {
+            let length = tails.partition_point(|&tail| starts[tail].1 < start);
+            previous[position] = length.checked_sub(1).map(|length| tails[length]);
+            if length == tails.len() {
+                tails.push(position);
+            } else {
+                tails[length] = position;
+            }
+        }
+        let mut in_place = vec![false; spans.len()];
+        let mut position = tails.last().copied();
+        while let Some(current) = position {
+            in_place[starts[current].0] = true;
+            position = previous[current];
+        }
+
+        let mut output = String::new();
+        // Set after a group written outside of its original place, so the next text is
+        // separated from it by a blank line
+        let mut separate = false;
+        let push = |output: &mut String, separate: &mut bool, text: &str| {
+            if *separate && !text.starts_with('\n') && !output.ends_with("\n\n") {
+                output.push('\n');
+            }
+            *separate = false;
+            output.push_str(text);
+        };
+
+        let mut cursor = 1;
+        for ((group, span), in_place) in self.groups.iter().zip(&spans).zip(in_place) {
+            let span = match span {
+                Some(span) if in_place => span,
+                _ => {
+                    if !output.is_empty() && !output.ends_with("\n\n") {
+                        output.push('\n');
+                    }
+                    output.push_str(&group.to_string());
+                    separate = true;
+                    continue;
+                }
+            };
+
+            for line in cursor..span.start {
+                if !in_group[line - 1] {
+                    push(
+                        &mut output,
+                        &mut separate,
+                        &format!("{}\n", lines[line - 1]),
+                    );
+                }
+            }
+            let raw = lines[span.start - 1..span.end - 1].join("\n") + "\n";
+            if group.spans.len() == 1 && self.matches_raw(group, &raw) {
+                push(&mut output, &mut separate, &raw);
+            } else {
+                push(&mut output, &mut separate, &group.to_string());
+            }
+            cursor = span.end;
+        }
+        for line in cursor..=lines.len() {
+            if !in_group[line - 1] {
+                push(
+                    &mut output,
+                    &mut separate,
+                    &format!("{}\n", lines[line - 1]),
+                );
+            }
+        }
+
+        output
+    }
+
+    /// Writes only the named groups to a file, in the given order.
+    ///
+    /// Groups are looked up as by [`TagParser::get_group`] and written in the same format as
+    /// `Display`.
+    ///
+    /// # Arguments
+    ///
+    /// * `names`: The names of the groups to write.
+    /// * `path`: A path to the file to write. An existing file is overwritten.
+    /// * `strict`: If `true`, a name without a matching group is an error of kind
+    ///   `NotFound` and nothing is written. Otherwise such names are skipped.
+    ///
+    /// returns: The names that were skipped because no group matched them.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use tag_parser::TagParser;
+    /// use std::path::Path;
+    ///
+    /// let data = include_str!("test_data.txt");
+    /// let parser = TagParser::from(data);
+    /// let path = Path::new("subset.txt");
+    /// let skipped = parser.write_groups_to_file(&["IDs", "Missing"], path, false).unwrap();
+    /// assert_eq!(skipped, vec!["Missing"]);
+    /// ```
+    pub fn write_groups_to_file(
+        &self,
+        names: &[&str],
+        path: &Path,
+        strict: bool,
+    ) -> io::Result<Vec<String>> {
+        let mut output = String::new();
+        let mut skipped = Vec::new();
+        for &name in names {
+            let Some(group) = self.get_group(name) else {
+                if strict {
+                    let message = format!("no group named `{name}`");
+                    return Err(io::Error::new(io::ErrorKind::NotFound, message));
+                }
+
+                skipped.push(name.to_string());
+                continue;
+            };
+
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            output.push_str(&self.format_group(group));
+        }
+
+        fs::write(path, output)?;
+        Ok(skipped)
+    }
+
+    /// Checks whether `original` comes back unchanged after being parsed and formatted again.
+    ///
+    /// Trailing newlines are ignored, since the formatted output always ends with exactly one.
+    /// This is mostly useful in tests.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// assert!(TagParser::round_trip_equal("[Generic]\nred_hair"));
+    /// assert!(!TagParser::round_trip_equal("[Generic] # comment\nred_hair"));
+    /// ```
+    pub fn round_trip_equal(original: &str) -> bool {
+        let original = original.trim_end_matches(['\r', '\n']);
+        let formatted = TagParser::from(original).to_string();
+        formatted.trim_end_matches('\n') == original
+    }
+
+    /// Guesses the format of `data`, to pick parse options for unknown input.
+    ///
+    /// Data with bracket headers is [`FormatStyle::Indented`] if every other line is indented,
+    /// and [`FormatStyle::Brackets`] otherwise. Data without headers is
+    /// [`FormatStyle::Compact`] if most lines look like `Name: tags`. Tabs or commas in compact
+    /// lines are taken as the tag separator. Comment prefixes are checked in the order `#`,
+    /// `//`, `;`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: A sample of the data, such as the first few lines of a file.
+    ///
+    /// returns: The guessed format. Data that matches nothing is assumed to use brackets.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::{FormatStyle, TagParser};
+    /// let hint = TagParser::detect_format("// Colors\nColors: red, blue\nShapes: circle");
+    /// assert_eq!(hint.style, FormatStyle::Compact);
+    /// assert_eq!(hint.comment_prefix, Some("//"));
+    /// assert_eq!(hint.tag_separator, Some(','));
+    /// ```
+    pub fn detect_format(data: &str) -> FormatHint {
+        let comment_prefix = ["#", "//", ";"].into_iter().find(|prefix| {
+            data.lines()
+                .any(|line| line.trim_start().starts_with(prefix))
+        });
+        let lines: Vec<&str> = data
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter(|line| {
+                comment_prefix.is_none_or(|prefix| !line.trim_start().starts_with(prefix))
+            })
+            .collect();
+
+        let (headers, content): (Vec<&str>, Vec<&str>) =
+            lines.iter().partition(|line| line.starts_with('['));
+        let compact: Vec<&str> = content
+            .iter()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(name, _)| !name.trim().is_empty() && !name.starts_with(char::is_whitespace))
+            .map(|(_, tags)| tags)
+            .collect();
+
+        let style = if !headers.is_empty() {
+            let indented = content
+                .iter()
+                .all(|line| line.starts_with(char::is_whitespace));
+            if indented && !content.is_empty() {
+                FormatStyle::Indented
+            } else {
+                FormatStyle::Brackets
+            }
+        } else if !compact.is_empty() && compact.len() * 2 >= content.len() {
+            FormatStyle::Compact
+        } else {
+            FormatStyle::Brackets
+        };
+
+        let tag_separator = if style != FormatStyle::Compact {
+            None
+        } else if compact.iter().any(|tags| tags.contains('\t')) {
+            Some('\t')
+        } else if compact.iter().any(|tags| tags.contains(',')) {
+            Some(',')
+        } else {
+            None
+        };
+
+        FormatHint {
+            style,
+            comment_prefix,
+            tag_separator,
+        }
+    }
+
+    /// Reads `data` line by line and returns what each line holds, without building groups.
+    ///
+    /// Lines are read with the default syntax, except that a header may leave out its closing
+    /// `]`. A tag line before any header is still a [`Event::Tag`], and each line of a
+    /// continued header is read on its own.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::{Event, TagParser};
+    /// let events: Vec<Event> = TagParser::events("[Colors]\n# Warm\nred\n").collect();
+    /// assert_eq!(
+    ///     events,
+    ///     vec![
+    ///         Event::GroupStart("Colors".to_string(), 1),
+    ///         Event::Comment("Warm".to_string(), 2),
+    ///         Event::Tag("red".to_string(), 3),
+    ///     ]
+    /// );
+    /// ```
+    pub fn events(data: &str) -> impl Iterator<Item = Event> + '_ {
+        let options = ParseOptions {
+            lenient_headers: true,
+            ..Default::default()
+        };
+        let parser = Self::unparsed(String::new(), options);
+
+        (1..).zip(data.lines()).map(move |(number, line)| {
+            if line.is_empty() {
+                Event::Blank(number)
+            } else if line.starts_with('#') {
+                Event::Comment(line.trim_start_matches('#').trim().to_string(), number)
+            } else if line.starts_with('[') {
+                let (name, _) = parser.parse_header(line).unwrap_or_default();
+                Event::GroupStart(name, number)
+            } else {
+                Event::Tag(parser.parse_tag(line), number)
+            }
+        })
+    }
+
+    /// Replaces the data with the current groups written in the tag file format.
+    ///
+    /// Use this after changing the groups, so that the data matches them again. The spans and
+    /// skipped lines refer to the old data, so they are cleared; call [`TagParser::parse`]
+    /// afterwards to fill them in from the new data. The changes recorded for
+    /// [`TagParser::undo`] are discarded too.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Colors]\nred");
+    /// parser.add_tag("Colors", "blue");
+    /// parser.rebuild_data();
+    /// parser.parse();
+    /// assert_eq!(parser.groups()[0].tags, vec!["red", "blue"]);
+    /// assert_eq!(parser.groups()[0].spans, vec![1..4]);
+    /// ```
+    pub fn rebuild_data(&mut self) {
+        for group in &mut self.groups {
+            group.spans.clear();
+        }
+        self.skipped.clear();
+        self.clear_history();
+        self.data = self.to_string();
+    }
+
+    /// Parses the data and extracts the groups and tags.
+    ///
+    /// The groups and tags are stored in the `groups` field. Any groups from a previous parse
+    /// are discarded first. If parsing fails, the groups parsed up to that point are kept and
+    /// the error is available from [`TagParser::error`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let data = include_str!("test_data.txt");
+    /// let mut parser = TagParser::from(data);
+    /// parser.parse();
+    /// let groups = parser.groups();
+    /// ```
+    pub fn parse(&mut self) {
+        // The error is kept in `self.error`
+        let _ = self.try_parse();
+    }
+
+    /// Parses the data like [`TagParser::parse`], returning any error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::{LimitPolicy, ParseError, ParseOptions, TagParser};
+    /// let options = ParseOptions {
+    ///     max_groups: Some(1),
+    ///     on_max_groups: LimitPolicy::Error,
+    ///     ..Default::default()
+    /// };
+    /// let mut parser = TagParser::with_options("[A]\n[B]", options);
+    /// assert_eq!(parser.try_parse(), Err(ParseError::TooManyGroups { max: 1 }));
+    /// ```
+    pub fn try_parse(&mut self) -> Result<(), ParseError> {
+        self.parse_up_to(self.data.len())
+    }
+
+    /// Parses only the start of the data, up to `max_bytes` bytes, for a quick partial view.
+    ///
+    /// Parsing stops at the end of the last line that fits completely within the limit. Like
+    /// [`TagParser::parse`], this discards the groups from a previous parse and records any
+    /// error.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bytes`: The maximum number of bytes of the data to read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Colors]\nred\n[Shapes]\ncircle");
+    /// parser.parse_prefix(16);
+    /// assert_eq!(parser.groups().len(), 1);
+    /// assert_eq!(parser.groups()[0].tags, vec!["red"]);
+    /// ```
+    pub fn parse_prefix(&mut self, max_bytes: usize) {
+        let end = if max_bytes >= self.data.len() {
+            self.data.len()
+        } else {
+            self.data.as_bytes()[..max_bytes]
+                .iter()
+                .rposition(|&byte| byte == b'\n')
+                .map_or(0, |newline| newline + 1)
+        };
+
+        // The error is kept in `self.error`
+        let _ = self.parse_up_to(end);
+    }
+
+    /// Parses groups from a reader one at a time, stopping as soon as `f` returns `true`.
+    ///
+    /// The data is parsed with the default options. Lines are read only up to the header of the
+    /// group after the one that stopped the parse, so the rest of the input is never read.
+    /// Spans refer to line numbers in the whole input.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader`: The source of the tag file.
+    /// * `f`: Called with each group once it is complete; returns `true` to stop parsing.
+    ///
+    /// returns: The groups parsed so far, including the one `f` stopped at, or an error if the
+    /// reader failed or the data could not be parsed. Parse errors have the kind
+    /// [`io::ErrorKind::InvalidData`] and wrap the [`ParseError`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let data = "[Colors]\nred\n[Shapes]\ncircle\n[Sizes]\nbig";
+    /// let groups = TagParser::parse_until(data.as_bytes(), |group| group.name == "Shapes").unwrap();
+    /// assert_eq!(groups.len(), 2);
+    /// assert_eq!(groups[1].tags, vec!["circle"]);
+    /// ```
+    pub fn parse_until<R: io::BufRead, F: FnMut(&Group) -> bool>(
+        reader: R,
+        f: F,
+    ) -> io::Result<Vec<Group>> {
+        Self::parse_reader(reader, |_| true, f)
+    }
+
+    /// Parses groups from a reader, keeping only the groups whose name passes `name_filter`.
+    ///
+    /// The data is parsed with the default options. The filter is called with the name of each
+    /// group as soon as its header has been read, and the lines of a group that fails it are
+    /// skipped without being parsed, so the group is never built. Aliases are not passed to the
+    /// filter. Spans refer
+    /// to line numbers in the whole input.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader`: The source of the tag file.
+    /// * `name_filter`: Returns `true` for the names of the groups to keep.
+    ///
+    /// returns: The groups that passed the filter, or an error if the reader failed or the data
+    /// could not be parsed. Parse errors have the kind [`io::ErrorKind::InvalidData`] and wrap
+    /// the [`ParseError`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let data = "[Colors]\nred\n[Shapes]\ncircle\n[Sizes]\nbig";
+    /// let groups = TagParser::parse_filtered(data.as_bytes(), |name| name.starts_with('S')).unwrap();
+    /// assert_eq!(groups.len(), 2);
+    /// assert_eq!(groups[0].tags, vec!["circle"]);
+    /// ```
+    pub fn parse_filtered<R: io::BufRead, F: Fn(&str) -> bool>(
+        reader: R,
+        name_filter: F,
+    ) -> io::Result<Vec<Group>> {
+        Self::parse_reader(reader, name_filter, |_| false)
+    }
+
+    /// Parses groups from a reader group by group, skipping the groups whose name fails `keep`
+    /// and stopping once `stop` returns `true` for a parsed group.
+    ///
+    /// `keep` is called as soon as a header has been read, and the lines of a group that fails
+    /// it are dropped until the next header without being parsed.
+    fn parse_reader<R: io::BufRead>(
+        reader: R,
+        keep: impl Fn(&str) -> bool,
+        mut stop: impl FnMut(&Group) -> bool,
+    ) -> io::Result<Vec<Group>> {
+        let mut parser = Self::unparsed(String::new(), ParseOptions::default());
+        let mut groups = Vec::new();
+        let mut chunk = String::new();
+        let mut chunk_start = 1;
+        // Whether the lines of the current group are dropped, because it failed the filter
+        let mut skipping = false;
+        // A read error ends the lines, and is returned before anything else is parsed
+        let error = Cell::new(None);
+        let mut lines = (1..).zip(
+            reader
+                .lines()
+                .map_while(|line| line.map_err(|read_error| error.set(Some(read_error))).ok()),
+        );
+
+        loop {
+            let next = lines.next();
+            if let Some(read_error) = error.take() {
+                return Err(read_error);
+            }
+            let at_header = next.as_ref().is_none_or(|(_, line)| line.starts_with('['));
+
+            // A header completes the group before it, so the buffered lines can be parsed
+            if at_header && !chunk.is_empty() {
+                parser
+                    .parse_str(&chunk, chunk_start)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+                chunk.clear();
+
+                for group in std::mem::take(&mut parser.groups) {
+                    let done = stop(&group);
+                    groups.push(group);
+                    if done {
+                        return Ok(groups);
+                    }
+                }
+            }
+
+            let Some((number, line)) = next else {
+                break;
+            };
+            if at_header {
+                let mut continued = String::new();
+                let (header, _) = join_header(
+                    &line,
+                    &mut lines.by_ref().inspect(|(_, next)| {
+                        continued.push_str(next);
+                        continued.push('\n');
+                    }),
+                );
+                if let Some(read_error) = error.take() {
+                    return Err(read_error);
+                }
+
+                // A header that cannot be parsed is kept, so that parsing reports it
+                skipping = parser
+                    .parse_header(&header)
+                    .is_some_and(|(name, _)| !name.is_empty() && !keep(&name));
+                if !skipping {
+                    chunk_start = number;
+                    chunk.push_str(&line);
+                    chunk.push('\n');
+                    chunk.push_str(&continued);
+                }
+            } else if !skipping {
+                if chunk.is_empty() {
+                    chunk_start = number;
+                }
+                chunk.push_str(&line);
+                chunk.push('\n');
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Discards the previous parse and parses the data up to the byte offset `end`.
+    fn parse_up_to(&mut self, end: usize) -> Result<(), ParseError> {
+        self.groups.clear();
+        self.skipped.clear();
+        self.clear_history();
+
+        let data = std::mem::take(&mut self.data);
+        let result = self.parse_str(&data[..end], 1);
+        self.data = data;
+
+        self.error = result.clone().err();
+        result
+    }
+
+    /// Returns the error that stopped the last parse, if any.
+    ///
+    /// [`TagParser::parse_additional`] counts as a parse, so appending data that parses
+    /// cleanly clears the error of an earlier parse.
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    /// Parses additional data into the parser without discarding the existing groups.
+    ///
+    /// Groups from `data` are added according to the duplicate policy in the parser's options:
+    /// with [`DuplicatePolicy::Merge`] their tags are appended to an existing group of the same
+    /// name, otherwise they are appended as new groups. The data is also appended to the
+    /// parser's source data. The changes recorded for [`TagParser::undo`] are discarded, and
+    /// [`TagParser::error`] is set to the error found in `data`, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: A string containing the additional tags.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Generic]\nred_hair");
+    /// parser.parse_additional("[IDs]\n102349");
+    /// assert_eq!(parser.groups().len(), 2);
+    /// ```
+    pub fn parse_additional(&mut self, data: &str) {
+        if !self.data.is_empty() && !self.data.ends_with('\n') {
+            self.data.push('\n');
+        }
+
+        self.clear_history();
+        let first_line = self.data.lines().count() + 1;
+        self.error = self.parse_str(data, first_line).err();
+        self.data.push_str(data);
+    }
+
+    /// Replaces a single line of the data and updates the groups to match.
+    ///
+    /// When both the old and the new line are tag lines inside a group, only that tag is
+    /// updated. Any other change, such as editing a header, falls back to parsing the whole data
+    /// again, as do options that change how lines are grouped, such as
+    /// [`DuplicatePolicy::Merge`], or groups that no longer hold the old line's tag at its place
+    /// because they were changed since the last parse. The rest of the data, including its line
+    /// endings, is left as it was. The changes recorded for [`TagParser::undo`] are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `line_index`: The 0-based index of the line to replace.
+    /// * `new_content`: The new content of the line.
+    ///
+    /// returns: `true` if the line was replaced, `false` if `line_index` is out of range.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let mut parser = TagParser::from("[Generic]\nred_hair\nsmile");
+    /// assert!(parser.reparse_line(2, "grin"));
+    /// assert_eq!(parser.groups()[0].tags, vec!["red_hair", "grin"]);
+    /// ```
+    pub fn reparse_line(&mut self, line_index: usize, new_content: &str) -> bool {
+        let Some(range) = self.line_range(line_index) else {
+            return false;
+        };
+
+        // Find which group and tag the line belongs to, before the data is changed
+        let lines: Vec<&str> = self.data.lines().collect();
+        let position = (self.options.supports_incremental_reparse()
+            && !lines.iter().any(|line| continues_header(line))
+            && is_tag_line(lines[line_index])
+            && is_tag_line(new_content))
+        .then(|| self.tag_position(&lines, line_index))
+        .flatten();
+
+        // Only the line itself changes, so the line endings of the data are kept
+        self.data.replace_range(range, new_content);
+
+        match position {
+            Some((group, tag)) => {
+                self.clear_history();
+                self.groups[group].tags[tag] = self.parse_tag(new_content);
+            }
+            None => self.parse(),
+        }
+
+        true
+    }
+
+    /// Returns the byte range of the line at `line_index` in the data, without its line ending.
+    fn line_range(&self, line_index: usize) -> Option<Range<usize>> {
+        let mut start = 0;
+        for (index, line) in self.data.split_inclusive('\n').enumerate() {
+            if index == line_index {
+                let content = match line.strip_suffix('\n') {
+                    Some(content) => content.strip_suffix('\r').unwrap_or(content),
+                    None => line,
+                };
+                return Some(start..start + content.len());
+            }
+            start += line.len();
+        }
+
+        None
+    }
+
+    /// Finds the group and tag index of the tag line at `line_index`.
+    ///
+    /// Returns `None` if the line is an orphan or the groups no longer match the data, including
+    /// when the tag at that place is not the one on the line.
+    fn tag_position(&self, lines: &[&str], line_index: usize) -> Option<(usize, usize)> {
+        let mut group: Option<(usize, String)> = None;
+        let mut headers = 0;
+        let mut tag = 0;
+        for &line in &lines[..line_index] {
+            if line.starts_with('[') {
+                let (name, _) = self.parse_header(line).unwrap_or_default();
+                group = (!name.is_empty()).then_some((headers, name));
+                headers += usize::from(group.is_some());
+                tag = 0;
+            } else if is_tag_line(line) {
+                tag += 1;
+            }
+        }
+
+        let (index, name) = group?;
+        let existing = self.groups.get(index)?;
+        (existing.name == name
+            && existing.tags.get(tag) == Some(&self.parse_tag(lines[line_index])))
+        .then_some((index, tag))
+    }
+
+    /// Parses `data` and adds the resulting groups to the existing ones.
+    ///
+    /// `first_line` is the line number of the first line of `data` within the parser's data.
+    fn parse_str(&mut self, data: &str, first_line: usize) -> Result<(), ParseError> {
+        let preprocessed: String;
+        let data = match self.options.line_preprocessor {
+            Some(preprocess) => {
+                preprocessed = data.lines().map(preprocess).collect::<Vec<_>>().join("\n");
+                preprocessed.as_str()
+            }
+            None => data,
+        };
+
+        let mut group = Group::default();
+        let mut tag_indent = None;
+        // The line after the header and the comments that directly follow it
+        let mut header_comments_end = 0;
+        // Groups before this index are in an earlier section and are never merged into
+        let mut section_start = 0;
+        let raw_lines: Vec<&str> = if self.options.raw_blocks {
+            data.lines().collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut lines = (first_line..).zip(data.lines());
+        if self.options.skip_front_matter {
+            self.skip_front_matter(&mut lines);
+        }
+
+        while let Some((number, line)) = lines.next() {
+            if self.options.reject_mixed_indentation && has_mixed_indentation(line) {
+                self.finish_group(
+                    std::mem::take(&mut group),
+                    &raw_lines,
+                    first_line,
+                    section_start,
+                );
+                return Err(ParseError::MixedIndentation { line: number });
+            }
+
+            let custom_header = self.parse_custom_header(line);
+            if line.is_empty() {
+                self.skip(number, line, SkipReason::Blank);
+            } else if custom_header.is_none() && line.starts_with('#') {
+                let text = line.trim_start_matches('#').trim();
+                if self.is_section_break(text) {
+                    self.finish_group(
+                        std::mem::take(&mut group),
+                        &raw_lines,
+                        first_line,
+                        section_start,
+                    );
+                    section_start = self.groups.len();
+                }
+
+                // Comments right after a header set the group's priority and description
+                if !group.name.is_empty() && number == header_comments_end {
+                    header_comments_end += 1;
+                    match parse_priority(text) {
+                        Some(priority) => {
+                            group.priority.get_or_insert(priority);
+                        }
+                        None => {
+                            group.description.get_or_insert_with(|| text.to_string());
+                        }
+                    }
+                }
+
+                self.skip(number, line, SkipReason::Comment);
+            } else if custom_header.is_none() && self.is_end_marker(line) {
+                let (name, _) = self.parse_header(line).unwrap_or_default();
+                let name = name.trim_start_matches('/').trim_start();
+                let matches = group.name == name || group.aliases.iter().any(|alias| alias == name);
+                self.finish_group(
+                    std::mem::take(&mut group),
+                    &raw_lines,
+                    first_line,
+                    section_start,
+                );
+                if self.options.end_markers == EndMarkerPolicy::Validate && !matches {
+                    return Err(ParseError::MismatchedEndMarker { line: number });
+                }
+
+                self.skip(number, line, SkipReason::EndMarker);
+            } else if custom_header.is_some()
+                || (!self.has_custom_headers()
+                    && line.starts_with('[')
+                    && self.is_header(line, lines.clone().map(|(_, next)| next)))
+            {
+                self.finish_group(
+                    std::mem::take(&mut group),
+                    &raw_lines,
+                    first_line,
+                    section_start,
+                );
+
+                let (header, end) = match custom_header {
+                    Some(_) => (Cow::Borrowed(line), number + 1),
+                    None => {
+                        let (header, last) = join_header(line, &mut lines);
+                        (header, last.unwrap_or(number) + 1)
+                    }
+                };
+
+                group.depth = match custom_header {
+                    Some(_) => 1,
+                    None => self.header_depth(&header),
+                };
+                (group.name, group.aliases) = match custom_header {
+                    Some(name) => (name, Vec::new()),
+                    None => self
+                        .parse_header(&header)
+                        .ok_or(ParseError::UnclosedHeader { line: number })?,
+                };
+                if self.at_group_limit(&group.name) {
+                    return self.group_limit_reached();
+                }
+                group.spans.push(number..end);
+                tag_indent = None;
+                header_comments_end = end;
+            } else if let Some(mut compact) = self.parse_compact(line, !group.name.is_empty()) {
+                // A compact group is complete on its own line and ends the current group
+                self.finish_group(
+                    std::mem::take(&mut group),
+                    &raw_lines,
+                    first_line,
+                    section_start,
+                );
+
+                if self.at_group_limit(&compact.name) {
+                    return self.group_limit_reached();
+                }
+                compact.depth = 1;
+                compact.spans.push(number..number + 1);
+                self.finish_group(compact, &raw_lines, first_line, section_start);
+            } else if group.name.is_empty() {
+                self.skip(number, line, SkipReason::Orphan);
+            } else if self.options.indented_tags && !indent_fits(line, &mut tag_indent) {
+                // De-indenting ends the group
+                self.finish_group(
+                    std::mem::take(&mut group),
+                    &raw_lines,
+                    first_line,
+                    section_start,
+                );
+                self.skip(number, line, SkipReason::Orphan);
+            } else {
+                group.tags.push(self.parse_tag(line));
+                group.spans[0].end = number + 1;
+            }
+        }
+
+        self.finish_group(group, &raw_lines, first_line, section_start);
+        Ok(())
+    }
+
+    /// Checks whether a line that starts with `[` is a header under the header policy.
+    ///
+    /// `following` yields the lines after `line`.
+    fn is_header<'a>(&self, line: &str, mut following: impl Iterator<Item = &'a str>) -> bool {
+        match self.options.header_policy {
+            HeaderPolicy::AlwaysHeader => true,
+            HeaderPolicy::RequireTagsLineBelow => following
+                .find(|next| !next.starts_with('#'))
+                .is_some_and(is_tag_line),
+            HeaderPolicy::HeuristicByContent => {
+                let (name, aliases) = self.parse_header(line).unwrap_or_default();
+                !std::iter::once(&name)
+                    .chain(&aliases)
+                    .any(|name| name.contains(char::is_whitespace))
+            }
+        }
+    }
+
+    /// Skips a leading shebang line and front-matter block, advancing `lines` past them.
+    fn skip_front_matter<'a>(
+        &mut self,
+        lines: &mut (impl Iterator<Item = (usize, &'a str)> + Clone),
+    ) {
+        let mut peek = lines.clone();
+        if let Some((number, line)) = peek.next().filter(|(_, line)| line.starts_with("#!")) {
+            self.skip(number, line, SkipReason::FrontMatter);
+            lines.next();
+        }
+
+        let mut peek = lines.clone();
+        if peek.next().is_none_or(|(_, line)| line.trim_end() != "---")
+            || !peek.any(|(_, line)| line.trim_end() == "---")
+        {
+            return;
+        }
+
+        // The first of these lines is the opening `---`, which is checked above
+        for (index, (number, line)) in lines.by_ref().enumerate() {
+            self.skip(number, line, SkipReason::FrontMatter);
+            if index > 0 && line.trim_end() == "---" {
+                break;
+            }
+        }
+    }
+
+    /// Checks whether a line is a `[/Name]` end marker, if end markers are enabled.
+    fn is_end_marker(&self, line: &str) -> bool {
+        self.options.end_markers != EndMarkerPolicy::Disabled && line.starts_with("[/")
+    }
+
+    /// Checks whether the text of a comment marks a section break.
+    fn is_section_break(&self, comment: &str) -> bool {
+        self.options
+            .section_break
+            .as_deref()
+            .is_some_and(|pattern| comment.starts_with(pattern))
+    }
+
+    /// Adds a parsed group, first storing its raw block and splitting off its negative tags if
+    /// those options are enabled.
+    ///
+    /// `raw_lines` holds the lines of the data being parsed, starting at line `first_line`, and
+    /// `section_start` is the index of the first group in the current section.
+    fn finish_group(
+        &mut self,
+        mut group: Group,
+        raw_lines: &[&str],
+        first_line: usize,
+        section_start: usize,
+    ) {
+        if let (true, Some(span)) = (self.options.raw_blocks, group.spans.first()) {
+            let lines = &raw_lines[span.start - first_line..span.end - first_line];
+            group.raw_block = Some(lines.join("\n") + "\n");
+        }
+        if let Some(prefix) = &self.options.negative_prefix {
+            for tag in &group.tags {
+                match tag.strip_prefix(prefix.as_str()) {
+                    Some(negative) => group.negative_tags.push(negative.to_string()),
+                    None => group.positive_tags.push(tag.clone()),
+                }
+            }
+        }
+
+        self.push_group(group, section_start);
+    }
+
+    /// Checks whether a group named `name` would go over the maximum number of groups.
+    fn at_group_limit(&self, name: &str) -> bool {
+        let Some(max) = self.options.max_groups else {
+            return false;
+        };
+
+        let merged = self.options.duplicates == DuplicatePolicy::Merge
+            && self
+                .groups
+                .iter()
+                .any(|group| self.is_duplicate_name(&group.name, name));
+        !name.is_empty() && !merged && self.groups.len() >= max
+    }
+
+    /// Ends parsing once the maximum number of groups is reached, as the options say.
+    fn group_limit_reached(&self) -> Result<(), ParseError> {
+        match (self.options.on_max_groups, self.options.max_groups) {
+            (LimitPolicy::Error, Some(max)) => Err(ParseError::TooManyGroups { max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `true` if headers are matched with a custom pattern instead of brackets.
+    fn has_custom_headers(&self) -> bool {
+        #[cfg(feature = "regex")]
+        if self.options.header_regex.is_some() {
+            return true;
+        }
+
+        false
+    }
+
+    /// Extracts the group name from a line matching the custom header pattern.
+    #[cfg(feature = "regex")]
+    fn parse_custom_header(&self, line: &str) -> Option<String> {
+        let captures = self.options.header_regex.as_ref()?.captures(line)?;
+        let name = captures.get(1).or_else(|| captures.get(0))?.as_str().trim();
+        Some(self.normalize_name(name))
+    }
+
+    /// Extracts the group name from a line matching the custom header pattern.
+    #[cfg(not(feature = "regex"))]
+    fn parse_custom_header(&self, _line: &str) -> Option<String> {
+        None
+    }
+
+    /// Applies the name options, such as lowercasing, to a trimmed group name.
+    fn normalize_name(&self, name: &str) -> String {
+        let name = if self.options.lowercase_group_names {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        };
+
+        match self.options.group_name_transform {
+            Some(transform) => transform(&name),
+            None => name,
+        }
+    }
+
+    /// Extracts the group name and its aliases from a header line.
+    ///
+    /// Returns `None` if the closing `]` is missing and lenient headers are off.
+    fn parse_header(&self, line: &str) -> Option<(String, Vec<String>)> {
+        let names = header_names(
+            line,
+            self.options.nested_groups,
+            self.options.lenient_headers,
+        )?;
+
+        let mut names = names.map(|name| self.normalize_name(name));
+        let name = names.next().unwrap_or_default();
+        Some((name, names.filter(|alias| !alias.is_empty()).collect()))
+    }
+
+    /// Formats a group, as its raw block if it still matches it and otherwise like `Display`.
+    fn format_group(&self, group: &Group) -> String {
+        match &group.raw_block {
+            Some(raw) if self.matches_raw(group, raw) => raw.clone(),
+            _ => group.to_string(),
+        }
+    }
+
+    /// Checks whether `raw` parses, with the parser's options, into a group with the same
+    /// contents as `group`.
+    fn matches_raw(&self, group: &Group, raw: &str) -> bool {
+        // The raw text has already been preprocessed and is not at the start of the data
+        let options = ParseOptions {
+            raw_blocks: false,
+            line_preprocessor: None,
+            skip_front_matter: false,
+            max_groups: None,
+            ..self.options.clone()
+        };
+        let parser = TagParser::with_options(raw, options);
+        let [parsed] = parser.groups.as_slice() else {
+            return false;
+        };
+
+        parsed.name == group.name
+            && parsed.depth == group.depth
+            && parsed.aliases == group.aliases
+            && parsed.tags == group.tags
+            && parsed.description == group.description
+            && parsed.priority == group.priority
+    }
+
+    /// Returns the depth of the group a header line starts.
+    fn header_depth(&self, line: &str) -> usize {
+        if self.options.nested_groups {
+            line.len() - line.trim_start_matches('[').len()
+        } else {
+            1
+        }
+    }
+
+    /// Extracts the tag from a tag line.
+    fn parse_tag(&self, line: &str) -> String {
+        self.trim_tag(tag_text(line, self.options.trailing_comments))
+    }
+
+    /// Removes a trailing comment from a tag line, if trailing comments are enabled.
+    fn strip_trailing_comment<'a>(&self, line: &'a str) -> &'a str {
+        strip_comment(line, self.options.trailing_comments)
+    }
+
+    /// Trims whitespace and the configured trim characters from both ends of a tag.
+    fn trim_tag(&self, tag: &str) -> String {
+        let trim_chars = &self.options.tag_trim_chars;
+        tag.trim_matches(|c: char| c.is_whitespace() || trim_chars.contains(&c))
+            .to_string()
+    }
+
+    /// Parses a compact `Name: tag tag` line into a group, if the compact format is enabled.
+    ///
+    /// When `in_group` is set, the line could also be a tag of the open bracket group, so it
+    /// needs a name without `/` followed by the separator and whitespace, like `Colors: red`.
+    fn parse_compact(&self, line: &str, in_group: bool) -> Option<Group> {
+        let format = self.options.compact_format.as_ref()?;
+        let line = self.strip_trailing_comment(line);
+        let (name, tags) = line.split_once(format.name_separator)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        if in_group
+            && (name.contains('/') || !tags.is_empty() && !tags.starts_with(char::is_whitespace))
+        {
+            return None;
+        }
+
+        let tags: Vec<&str> = if format.tag_separators.is_empty() {
+            tags.split_whitespace().collect()
+        } else {
+            tags.split(format.tag_separators.as_slice()).collect()
+        };
+
+        Some(Group {
+            name: self.normalize_name(name),
+            tags: tags
+                .into_iter()
+                .map(|tag| self.trim_tag(tag))
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Records a line that was not turned into a group or a tag.
+    fn skip(&mut self, line: usize, text: &str, reason: SkipReason) {
+        self.skipped.push(SkippedLine {
+            line,
+            text: text.to_string(),
+            reason,
+        });
+    }
+
+    /// Checks whether two group names count as the same group when merging.
+    fn is_duplicate_name(&self, a: &str, b: &str) -> bool {
+        a == b || (self.options.merge_ignore_case && a.to_lowercase() == b.to_lowercase())
+    }
+
+    /// Adds a parsed group, merging it into an existing group if the duplicate policy says so.
+    ///
+    /// Only groups from `section_start` onward are merged into. Groups without a name are
+    /// dropped.
+    fn push_group(&mut self, group: Group, section_start: usize) {
+        if group.name.is_empty() {
+            return;
+        }
+
+        if self.options.duplicates == DuplicatePolicy::Merge {
+            let existing = self.groups[section_start..]
+                .iter()
+                .position(|existing| self.is_duplicate_name(&existing.name, &group.name));
+            if let Some(index) = existing.map(|index| section_start + index) {
+                let existing = &mut self.groups[index];
+                existing.tags.extend(group.tags);
+                existing.positive_tags.extend(group.positive_tags);
+                existing.negative_tags.extend(group.negative_tags);
+                existing.spans.extend(group.spans);
+                if existing.description.is_none() {
+                    existing.description = group.description;
+                }
+                if existing.priority.is_none() {
+                    existing.priority = group.priority;
+                }
+                for alias in group.aliases {
+                    if !existing.aliases.contains(&alias) {
+                        existing.aliases.push(alias);
+                    }
+                }
+                return;
+            }
+        }
+
+        self.groups.push(group);
+    }
+}
+
+/// Decodes raw bytes as UTF-8, replacing invalid sequences.
+#[cfg(not(feature = "encoding"))]
+fn decode(bytes: &[u8]) -> Result<String, ParseError> {
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Decodes raw bytes using their byte order mark, falling back to UTF-8 and then Latin-1.
+#[cfg(feature = "encoding")]
+fn decode(bytes: &[u8]) -> Result<String, ParseError> {
+    if let Some(bytes) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return Ok(String::from_utf8_lossy(bytes).into_owned());
+    }
+
+    let utf16: Option<fn([u8; 2]) -> u16> = match bytes {
+        [0xFF, 0xFE, ..] => Some(u16::from_le_bytes),
+        [0xFE, 0xFF, ..] => Some(u16::from_be_bytes),
+        _ => None,
+    };
+    if let Some(from_bytes) = utf16 {
+        let invalid = ParseError::InvalidEncoding { encoding: "UTF-16" };
+        let bytes = &bytes[2..];
+        if !bytes.len().is_multiple_of(2) {
+            return Err(invalid);
+        }
+
+        let units = bytes
+            .chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]));
+        return char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|_| invalid);
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(data) => Ok(data.to_string()),
+        Err(_) => Ok(bytes.iter().map(|&byte| byte as char).collect()),
+    }
+}
+
+/// Splits a header line into its trimmed names, borrowed from the line. The first name is the
+/// group name and the rest are aliases.
+///
+/// A trailing comment is ignored. With `nested`, every leading `[` and trailing `]` is removed
+/// instead of one of each. Returns `None` if the header has no closing `]` and `lenient` is off.
+fn header_names(line: &str, nested: bool, lenient: bool) -> Option<impl Iterator<Item = &str>> {
+    let line = line.split('#').next().unwrap().trim();
+    let line = if nested {
+        line.trim_start_matches('[')
+    } else {
+        line.strip_prefix('[').unwrap_or(line)
+    };
+    let line = match line.strip_suffix(']') {
+        Some(line) if nested => line.trim_end_matches(']'),
+        Some(line) => line,
+        None if lenient => line,
+        None => return None,
+    };
+
+    Some(line.split('|').map(str::trim))
+}
+
+/// Returns the text of a tag line, without the escape of a leading `\[` and, if
+/// `trailing_comments` is on, without a trailing comment. The text is not trimmed.
+fn tag_text(line: &str, trailing_comments: bool) -> &str {
+    // An escaped bracket (`\[`) at the start of a line is a literal tag, not a header
+    let line = match line.strip_prefix('\\') {
+        Some(rest) if rest.starts_with('[') => rest,
+        _ => line,
+    };
+
+    strip_comment(line, trailing_comments)
+}
+
+/// Removes a trailing comment from a line if `trailing_comments` is on.
+fn strip_comment(line: &str, trailing_comments: bool) -> &str {
+    if trailing_comments {
+        line.split('#').next().unwrap()
+    } else {
+        line
+    }
+}
+
+/// Checks whether a line is a tag line rather than a blank, comment or header line.
+fn is_tag_line(line: &str) -> bool {
+    !line.is_empty() && !line.contains('\n') && !line.starts_with(['#', '['])
+}
+
+/// Reads the priority from the text of a `# priority: 2` comment.
+fn parse_priority(comment: &str) -> Option<i32> {
+    comment.strip_prefix("priority:")?.trim().parse().ok()
+}
+
+/// Checks whether the leading whitespace of a line has both tabs and spaces.
+fn has_mixed_indentation(line: &str) -> bool {
+    let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+    indent.contains(' ') && indent.contains('\t')
+}
+
+/// Checks whether a header line ends with a backslash, continuing the header on the next line.
+///
+/// A trailing comment is ignored, and a header that is already closed by a `]` never continues.
+fn continues_header(line: &str) -> bool {
+    let header = header_text(line);
+    line.starts_with('[') && header.ends_with('\\') && !header.contains(']')
+}
+
+/// Joins a header line with the lines it continues on, taking them from `lines`.
+///
+/// A header ending in a backslash continues on the next line. The header is only copied if it
+/// continues.
+///
+/// returns: The whole header, and the number of the last line taken from `lines`, if any.
+fn join_header<'a>(
+    line: &'a str,
+    lines: &mut impl Iterator<Item = (usize, impl AsRef<str>)>,
+) -> (Cow<'a, str>, Option<usize>) {
+    let mut header = Cow::Borrowed(line);
+    let mut last = None;
+    while continues_header(&header) {
+        let header = header.to_mut();
+        header.truncate(header_text(header).len() - 1);
+        let Some((number, next)) = lines.next() else {
+            break;
+        };
+        header.push_str(next.as_ref().trim_start());
+        last = Some(number);
+    }
+
+    (header, last)
+}
+
+/// Returns a header line without its trailing comment and trailing whitespace.
+fn header_text(line: &str) -> &str {
+    line.split('#').next().unwrap().trim_end()
+}
+
+/// Checks whether a tag line is indented enough to belong to the current group.
+///
+/// The first indented line sets `tag_indent` for the rest of the group.
+fn indent_fits(line: &str, tag_indent: &mut Option<usize>) -> bool {
+    let indent = line.len() - line.trim_start().len();
+    let required = *tag_indent.get_or_insert(indent);
+    indent > 0 && indent >= required
+}
+
+/// Returns the minimum, maximum and mean character length of `tags`.
+fn len_stats<T: AsRef<str>>(tags: &[T]) -> (usize, usize, f64) {
+    if tags.is_empty() {
+        return (0, 0, 0.0);
+    }
+
+    let lengths: Vec<usize> = tags
+        .iter()
+        .map(|tag| tag.as_ref().chars().count())
+        .collect();
+    let min = lengths.iter().copied().min().unwrap_or_default();
+    let max = lengths.iter().copied().max().unwrap_or_default();
+    let mean = lengths.iter().sum::<usize>() as f64 / lengths.len() as f64;
+    (min, max, mean)
+}
+
+/// Turns a group name into an ASCII slug, as described on [`TagParser::slugged_names`].
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    if slug.ends_with('-') {
+        slug.pop();
+    }
+
+    if slug.is_empty() {
+        let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        slug = format!("group-{hash:016x}");
+    }
+
+    slug
+}
+
+/// Quotes a CSV field if it contains a comma, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes a TOML key, quoting it unless it is a valid bare key.
+#[cfg(feature = "toml")]
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+/// Writes a TOML basic string, escaping quotes, backslashes and control characters.
+#[cfg(feature = "toml")]
+fn toml_string(value: &str) -> String {
+    let mut string = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\n' => string.push_str("\\n"),
+            '\r' => string.push_str("\\r"),
+            '\t' => string.push_str("\\t"),
+            c if c.is_control() => string.push_str(&format!("\\u{:04X}", c as u32)),
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+    string
+}
+
+impl fmt::Display for TagParser {
+    /// Formats the groups in the tag file format, separated by blank lines.
+    ///
+    /// The output ends with exactly one trailing newline, or is empty if there are no groups.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Generic]\nred_hair\n[IDs]\n102349\n\n\n");
+    /// assert_eq!(parser.to_string(), "[Generic]\nred_hair\n\n[IDs]\n102349\n");
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, group) in self.groups.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            f.write_str(&self.format_group(group))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl IntoIterator for TagParser {
+    type Item = (String, Vec<String>);
+    type IntoIter = std::iter::Map<std::vec::IntoIter<Group>, fn(Group) -> (String, Vec<String>)>;
+
+    /// Consumes the parser and yields the name and tags of each group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let parser = TagParser::from("[Generic]\nred_hair\n[IDs]\n102349");
+    /// let pairs: Vec<(String, Vec<String>)> = parser.into_iter().collect();
+    /// assert_eq!(pairs[0], ("Generic".to_string(), vec!["red_hair".to_string()]));
+    /// ```
+    fn into_iter(self) -> Self::IntoIter {
+        self.groups
+            .into_iter()
+            .map(|group| (group.name, group.tags))
+    }
+}
+
+impl From<&str> for TagParser {
+    /// Creates a new `TagParser` instance from a string.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: A string containing the tags.
+    ///
+    /// returns: A new `TagParser` instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let data = include_str!("test_data.txt");
+    /// let parser = TagParser::from(data);
+    /// let groups = parser.groups();
+    /// ```
+    fn from(data: &str) -> Self {
+        Self::with_options(data, ParseOptions::default())
+    }
+}
+
+impl From<String> for TagParser {
+    /// Creates a new `TagParser` instance from a string.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: A string containing the tags.
+    ///
+    /// returns: A new `TagParser` instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tag_parser::TagParser;
+    /// let data = include_str!("test_data.txt");
+    /// let parser = TagParser::from(data);
+    /// let groups = parser.groups();
+    /// ```
+    fn from(data: String) -> Self {
+        Self::from(data.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags.len(), 3);
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[1].tags.len(), 1);
+        assert_eq!(groups[2].name, "EmptyGroup");
+        assert_eq!(groups[2].tags.len(), 0);
+    }
+
+    #[test]
+    fn multiple_groups_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags.len(), 3);
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[1].tags.len(), 1);
+    }
+
+    #[test]
+    fn tag_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags.len(), 3);
+
+        let tags = &groups[0].tags;
+        assert_eq!(tags[0].as_str(), "red_hair female dress");
+        assert_eq!(tags[1].as_str(), "dancing fire smile");
+        assert_eq!(tags[2].as_str(), "進撃の巨人");
+
+        let tags = &groups[1].tags;
+        assert_eq!(tags[0].as_str(), "102349");
+    }
+
+    #[test]
+    fn parse_additional_test() {
+        let options = ParseOptions {
+            duplicates: DuplicatePolicy::Merge,
+            ..Default::default()
+        };
+        let mut parser = TagParser::with_options("[Generic]\nred_hair\n\n[IDs]\n102349", options);
+        parser.parse_additional("[Generic]\nsmile\n\n[Extra]\nfire");
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags, vec!["red_hair", "smile"]);
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[2].name, "Extra");
+        assert_eq!(groups[2].tags, vec!["fire"]);
+
+        let mut parser = TagParser::from("[Generic]\nred_hair");
+        parser.parse_additional("[Generic]\nsmile");
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].tags, vec!["red_hair"]);
+        assert_eq!(groups[1].tags, vec!["smile"]);
+
+        // Each append replaces the error of the previous parse
+        let mut parser = TagParser::from("[A]\nred");
+        parser.parse_additional("[B\n");
+        assert_eq!(
+            parser.error(),
+            Some(&ParseError::UnclosedHeader { line: 3 })
+        );
+        parser.parse_additional("[C]\nx\n");
+        assert_eq!(parser.error(), None);
+        assert_eq!(parser.groups().last().unwrap().name, "C");
+    }
+
+    #[test]
+    fn numeric_tags_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[1].numeric_tags(false), Ok(vec![102349]));
+
+        let group = Group {
+            name: "Mixed".to_string(),
+            tags: vec!["1".to_string(), "two".to_string(), "3".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(group.numeric_tags(true), Ok(vec![1, 3]));
+        assert!(group.numeric_tags(false).is_err());
+    }
+
+    #[test]
+    fn lowercase_group_names_test() {
+        let data = include_str!("test_data.txt");
+        let options = ParseOptions {
+            lowercase_group_names: true,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups[0].name, "generic");
+        assert_eq!(groups[0].tags[0], "red_hair female dress");
+        assert_eq!(groups[1].name, "ids");
+
+        let parser = TagParser::with_options(data, ParseOptions::default());
+        assert_eq!(parser.groups()[0].name, "Generic");
+    }
+
+    #[test]
+    fn escaped_bracket_test() {
+        let data = "[RealHeader]\n\\[not a header]\nred_hair\n\\[another # comment";
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "RealHeader");
+        assert_eq!(
+            groups[0].tags,
+            vec!["[not a header]", "red_hair", "[another"]
+        );
+    }
+
+    #[test]
+    fn empty_groups_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        assert_eq!(parser.empty_groups(), vec!["EmptyGroup"]);
+
+        parser.prune_empty_groups();
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[1].name, "IDs");
+        assert!(parser.empty_groups().is_empty());
+    }
+
+    #[test]
+    fn from_dir_test() {
+        let dir = std::env::temp_dir().join(format!("tag_parser_from_dir_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b.txt"), "[IDs]\n102349\n[Generic]\nsmile\n").unwrap();
+        fs::write(dir.join("a.txt"), "[Generic]\nred_hair female dress").unwrap();
+        fs::write(dir.join("ignored.md"), "[Ignored]\ntag").unwrap();
+
+        let parser = TagParser::from_dir(&dir).unwrap();
+
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags, vec!["red_hair female dress", "smile"]);
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[1].tags, vec!["102349"]);
+        assert_eq!(parser.get_group("Generic"), Some(&groups[0]));
+        assert_eq!(parser.error(), None);
+
+        // An error in one file is not hidden by the files after it
+        fs::write(dir.join("a.txt"), "[Generic\nred_hair").unwrap();
+        let parser = TagParser::from_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            parser.error(),
+            Some(&ParseError::UnclosedHeader { line: 1 })
+        );
+        assert_eq!(parser.groups().len(), 2);
+    }
+
+    #[test]
+    fn to_csv_test() {
+        let parser =
+            TagParser::from("[Generic]\nred, blue\nsay \"hi\"\n\n[IDs]\n102349\n[EmptyGroup]");
+        assert_eq!(
+            parser.to_csv(),
+            "Generic,\"red, blue\"\nGeneric,\"say \"\"hi\"\"\"\nIDs,102349\nEmptyGroup,\n"
+        );
+    }
+
+    #[test]
+    fn union_intersection_test() {
+        let group = |name: &str, tags: &[&str]| Group {
+            name: name.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let a = group("A", &["red", "blue", "red", "fire"]);
+        let b = group("B", &["fire", "green", "blue"]);
+        assert_eq!(a.union(&b), vec!["red", "blue", "fire", "green"]);
+        assert_eq!(a.intersection(&b), vec!["blue", "fire"]);
+
+        let c = group("C", &["smile"]);
+        assert_eq!(a.union(&c), vec!["red", "blue", "fire", "smile"]);
+        assert!(a.intersection(&c).is_empty());
+    }
+
+    #[test]
+    fn stats_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert_eq!(
+            parser.stats(),
+            ParseStats {
+                group_count: 3,
+                tag_count: 4,
+                average_tags_per_group: 4.0 / 3.0,
+                comment_count: 3,
+                orphan_count: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn display_round_trip_test() {
+        let canonical =
+            "[Generic]\nred_hair female dress\n\\[bracketed]\n\n[IDs]\n102349\n\n[EmptyGroup]\n";
+        let parser = TagParser::from(canonical);
+        assert_eq!(parser.to_string(), canonical);
+
+        let without_newline = canonical.trim_end();
+        assert_eq!(TagParser::from(without_newline).to_string(), canonical);
+        assert!(TagParser::round_trip_equal(canonical));
+        assert!(TagParser::round_trip_equal(without_newline));
+        assert!(TagParser::round_trip_equal(&format!("{canonical}\n\n")));
+
+        let data = include_str!("test_data.txt");
+        assert!(!TagParser::round_trip_equal(data));
+        assert!(TagParser::from(data)
+            .to_string()
+            .ends_with("[EmptyGroup]\n"));
+    }
+
+    #[test]
+    fn groups_with_prefix_test() {
+        let parser = TagParser::from("[Colors]\nred\n[collections]\n102349\n[IDs]\n1\n[Col]");
+        let names: Vec<&str> = parser
+            .groups_with_prefix("COL")
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Colors", "collections", "Col"]);
+        assert_eq!(parser.groups_with_prefix("colo").len(), 1);
+        assert!(parser.groups_with_prefix("x").is_empty());
+        assert_eq!(parser.groups_with_prefix("").len(), 4);
+    }
+
+    #[test]
+    fn weighted_tags_test() {
+        let group = Group {
+            name: "Prompt".to_string(),
+            tags: vec![
+                "(red_hair:1.2)".to_string(),
+                "( smile : 0.5 )".to_string(),
+                "dress".to_string(),
+                "(fire:much)".to_string(),
+                "(:1.5)".to_string(),
+                "(dancing".to_string(),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            group.weighted_tags(),
+            vec![
+                ("red_hair".to_string(), 1.2),
+                ("smile".to_string(), 0.5),
+                ("dress".to_string(), 1.0),
+                ("(fire:much)".to_string(), 1.0),
+                ("(:1.5)".to_string(), 1.0),
+                ("(dancing".to_string(), 1.0),
+            ]
+        );
+        assert_eq!(group.tags[0], "(red_hair:1.2)");
+    }
+
+    #[test]
+    fn duplicate_group_bodies_test() {
+        let data = "[Generic]\nred_hair\nsmile\n[IDs]\n102349\n[Common]\nsmile\nred_hair\nsmile\n[Other]\nred_hair";
+        let parser = TagParser::from(data);
+        assert_eq!(
+            parser.duplicate_group_bodies(),
+            vec![vec!["Generic", "Common"]]
+        );
+
+        let data = include_str!("test_data.txt");
+        assert!(TagParser::from(data).duplicate_group_bodies().is_empty());
+    }
+
+    #[test]
+    fn skipped_lines_test() {
+        let mut parser = TagParser::from("orphan\n\n# comment\n[Generic]\nred_hair\n");
+        parser.parse_additional("stray\n[IDs]\n  \n102349");
+
+        let skipped: Vec<(usize, &str, SkipReason)> = parser
+            .skipped()
+            .iter()
+            .map(|skipped| (skipped.line, skipped.text.as_str(), skipped.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                (1, "orphan", SkipReason::Orphan),
+                (2, "", SkipReason::Blank),
+                (3, "# comment", SkipReason::Comment),
+                (6, "stray", SkipReason::Orphan),
+            ]
+        );
+
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let reasons: Vec<SkipReason> = parser.skipped().iter().map(|s| s.reason).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                SkipReason::Comment,
+                SkipReason::Blank,
+                SkipReason::Orphan,
+                SkipReason::Blank,
+                SkipReason::Blank,
+                SkipReason::Comment,
+                SkipReason::Blank,
+                SkipReason::Comment,
+            ]
+        );
+    }
+
+    #[test]
+    fn into_iter_test() {
+        let data = include_str!("test_data.txt");
+        let pairs: Vec<(String, Vec<String>)> = TagParser::from(data).into_iter().collect();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs[0].0, "Generic");
+        assert_eq!(
+            pairs[0].1,
+            vec!["red_hair female dress", "dancing fire smile", "進撃の巨人"]
+        );
+        assert_eq!(pairs[1], ("IDs".to_string(), vec!["102349".to_string()]));
+        assert_eq!(pairs[2], ("EmptyGroup".to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn from_bytes_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from_bytes(data.as_bytes()).unwrap();
+        assert_eq!(parser.groups().len(), 3);
+        assert_eq!(parser.groups()[0].tags[2], "進撃の巨人");
+
+        let parser = TagParser::from_bytes(b"[Caf\xE9]\nna\xEFve").unwrap();
+        let group = &parser.groups()[0];
+        if cfg!(feature = "encoding") {
+            assert_eq!(group.name, "Café");
+            assert_eq!(group.tags[0], "naïve");
+        } else {
+            assert_eq!(group.name, "Caf\u{FFFD}");
+            assert_eq!(group.tags[0], "na\u{FFFD}ve");
+        }
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn from_bytes_utf16_test() {
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in "[IDs]\n102349".encode_utf16() {
+            bytes.extend(unit.to_le_bytes());
+        }
+        let parser = TagParser::from_bytes(&bytes).unwrap();
+        assert_eq!(parser.groups()[0].name, "IDs");
+        assert_eq!(parser.groups()[0].tags[0], "102349");
+
+        bytes.push(0);
+        assert_eq!(
+            TagParser::from_bytes(&bytes).unwrap_err(),
+            ParseError::InvalidEncoding { encoding: "UTF-16" }
+        );
+    }
+
+    #[test]
+    fn rename_tag_test() {
+        let data = "[Generic]\nred_hair\nsmile\n[Other]\nsmile\ngrin\n[IDs]\n102349";
+        let mut parser = TagParser::from(data);
+        assert_eq!(parser.rename_tag("smile", "grin", false), 2);
+        assert_eq!(parser.groups()[0].tags, vec!["red_hair", "grin"]);
+        assert_eq!(parser.groups()[1].tags, vec!["grin", "grin"]);
+
+        let mut parser = TagParser::from(data);
+        assert_eq!(parser.rename_tag("smile", "grin", true), 2);
+        assert_eq!(parser.groups()[1].tags, vec!["grin"]);
+        assert_eq!(parser.rename_tag("missing", "grin", true), 0);
+    }
+
+    #[test]
+    fn trailing_comments_test() {
+        let data = "# comment\n[Colors] # header comment\nred #ff0000\nC# F#";
+
+        let parser = TagParser::from(data);
+        assert_eq!(parser.groups()[0].tags, vec!["red", "C"]);
+
+        let options = ParseOptions {
+            trailing_comments: false,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "Colors");
+        assert_eq!(groups[0].tags, vec!["red #ff0000", "C# F#"]);
+        assert_eq!(parser.stats().comment_count, 1);
+    }
+
+    #[test]
+    fn move_group_test() {
+        let names = |parser: &TagParser| -> Vec<String> {
+            parser
+                .groups()
+                .iter()
+                .map(|group| group.name.clone())
+                .collect()
+        };
+
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        assert!(parser.move_group("Generic", 1));
+        assert_eq!(names(&parser), vec!["IDs", "Generic", "EmptyGroup"]);
+
+        assert!(parser.move_group("EmptyGroup", 0));
+        assert_eq!(names(&parser), vec!["EmptyGroup", "IDs", "Generic"]);
+
+        assert!(parser.move_group("EmptyGroup", 100));
+        assert_eq!(names(&parser), vec!["IDs", "Generic", "EmptyGroup"]);
+        assert_eq!(parser.groups()[1].tags.len(), 3);
+
+        assert!(!parser.move_group("Missing", 0));
+
+        // Aliases find the group, and a group already in place records nothing
+        let mut parser = TagParser::from("[A | Alpha]\n[B]");
+        parser.record_history(true);
+        assert!(parser.move_group("Alpha", 1));
+        assert_eq!(names(&parser), vec!["B", "A"]);
+        assert!(parser.move_group("B", 0));
+        assert!(parser.move_group("A", 100));
+        assert!(parser.undo());
+        assert_eq!(names(&parser), vec!["A", "B"]);
+        assert!(!parser.undo());
+    }
+
+    #[test]
+    fn chunks_test() {
+        let group = Group {
+            name: "Generic".to_string(),
+            tags: ["red_hair", "female", "dress", "dancing", "smile"]
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect(),
+            ..Default::default()
+        };
+
+        let chunks = group.chunks(2);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].name, "Generic (1)");
+        assert_eq!(chunks[0].tags, vec!["red_hair", "female"]);
+        assert_eq!(chunks[1].name, "Generic (2)");
+        assert_eq!(chunks[1].tags, vec!["dress", "dancing"]);
+        assert_eq!(chunks[2].name, "Generic (3)");
+        assert_eq!(chunks[2].tags, vec!["smile"]);
+
+        assert!(group.chunks(0).is_empty());
+    }
+
+    #[test]
+    fn tag_trim_chars_test() {
+        let data = "[Generic]\n*red*\n  [dress] # comment\n* smile *\nred*hair";
+
+        let parser = TagParser::from(data);
+        assert_eq!(parser.groups()[0].tags[0], "*red*");
+
+        let options = ParseOptions {
+            tag_trim_chars: vec!['*', '[', ']'],
+            ..Default::default()
+        };
+        let parser = TagParser::with_options(data, options);
+        assert_eq!(parser.groups()[0].name, "Generic");
+        assert_eq!(
+            parser.groups()[0].tags,
+            vec!["red", "dress", "smile", "red*hair"]
+        );
+    }
+
+    #[test]
+    fn reparse_line_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+
+        // Index 6 is "dancing fire smile" in the Generic group
+        assert!(parser.reparse_line(6, "dancing fire grin # edited"));
+        assert_eq!(parser.groups()[0].tags[1], "dancing fire grin");
+        assert_eq!(parser.groups().len(), 3);
+
+        // Index 10 is the "[IDs ]" header
+        assert!(parser.reparse_line(10, "[Numbers]"));
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].tags[1], "dancing fire grin");
+        assert_eq!(groups[1].name, "Numbers");
+        assert_eq!(groups[1].tags, vec!["102349"]);
+
+        // Turning a tag line into a header splits the group
+        assert!(parser.reparse_line(7, "[Anime]"));
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups[0].tags.len(), 2);
+        assert_eq!(groups[1].name, "Anime");
+        assert!(groups[1].tags.is_empty());
+
+        assert!(!parser.reparse_line(100, "tag"));
+        assert_eq!(
+            parser.to_string(),
+            TagParser::from(parser.data.as_str()).to_string()
+        );
+    }
+
+    #[test]
+    fn reparse_line_after_changes_test() {
+        // A removed tag shifts the others, so the line no longer matches its place
+        let mut parser = TagParser::from("[A]\nred\nblue\ngreen");
+        parser.record_history(true);
+        parser.remove_tag("A", "red");
+        assert!(parser.reparse_line(1, "crimson"));
+        assert_eq!(parser.groups()[0].tags, vec!["crimson", "blue", "green"]);
+        assert!(!parser.undo());
+
+        let mut parser = TagParser::from("[A]\nred\nblue\ngreen");
+        parser.record_history(true);
+        parser.set_tags("A", vec!["x".to_string(), "y".to_string(), "z".to_string()]);
+        assert!(parser.reparse_line(2, "BLUE"));
+        assert_eq!(parser.groups()[0].tags, vec!["red", "BLUE", "green"]);
+        assert!(!parser.undo());
+
+        let mut parser = TagParser::from("[A]\nred\n[B]\nblue");
+        parser.record_history(true);
+        parser.move_group("B", 0);
+        assert!(parser.reparse_line(1, "crimson"));
+        let groups = parser.groups();
+        assert_eq!(groups[0].name, "A");
+        assert_eq!(groups[0].tags, vec!["crimson"]);
+        assert_eq!(groups[1].tags, vec!["blue"]);
+        assert!(!parser.undo());
+
+        // The in-place update discards the history too
+        let mut parser = TagParser::from("[A]\nred\nblue\n[B]\ngreen");
+        parser.record_history(true);
+        parser.add_tag("B", "yellow");
+        assert!(parser.reparse_line(1, "crimson"));
+        assert_eq!(parser.groups()[0].tags, vec!["crimson", "blue"]);
+        assert_eq!(parser.groups()[1].tags, vec!["green", "yellow"]);
+        assert!(!parser.undo());
+    }
+
+    #[test]
+    fn reparse_line_crlf_test() {
+        let mut parser = TagParser::from("[A]\r\nred\r\nblue\r\n");
+        assert!(parser.reparse_line(1, "green"));
+        assert_eq!(parser.data, "[A]\r\ngreen\r\nblue\r\n");
+        assert_eq!(parser.groups()[0].tags, vec!["green", "blue"]);
+
+        // A header change parses everything again, but keeps the line endings
+        assert!(parser.reparse_line(0, "[B]"));
+        assert_eq!(parser.data, "[B]\r\ngreen\r\nblue\r\n");
+        assert_eq!(parser.groups()[0].name, "B");
+
+        // Options that need a full parse still give the same groups
+        let mut parser = TagParser::with_options(
+            "[A]\nred\n[A]\nblue",
+            ParseOptions {
+                duplicates: DuplicatePolicy::Merge,
+                ..Default::default()
+            },
+        );
+        assert!(parser.reparse_line(3, "green"));
+        assert_eq!(parser.groups().len(), 1);
+        assert_eq!(parser.groups()[0].tags, vec!["red", "green"]);
+    }
+
+    #[test]
+    fn header_alias_test() {
+        let data = "[Generic | Common|Default]\nred_hair\n[IDs]\n102349\n[Empty|]";
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].aliases, vec!["Common", "Default"]);
+        assert!(groups[1].aliases.is_empty());
+        assert_eq!(groups[2].name, "Empty");
+        assert!(groups[2].aliases.is_empty());
+
+        for name in ["Generic", "Common", "Default"] {
+            assert_eq!(parser.get_group(name).unwrap().tags, vec!["red_hair"]);
+        }
+        assert_eq!(parser.get_group("IDs").unwrap().tags, vec!["102349"]);
+        assert!(parser.get_group("common").is_none());
+        assert!(parser
+            .to_string()
+            .starts_with("[Generic|Common|Default]\nred_hair\n"));
+    }
+
+    #[test]
+    fn group_at_line_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let name_at = |line| parser.group_at_line(line).map(|group| group.name.as_str());
+
+        assert_eq!(name_at(1), None); // comment
+        assert_eq!(name_at(3), None); // orphan
+        assert_eq!(name_at(5), Some("Generic"));
+        assert_eq!(name_at(6), Some("Generic"));
+        assert_eq!(name_at(8), Some("Generic"));
+        assert_eq!(name_at(9), None); // blank
+        assert_eq!(name_at(10), None); // comment
+        assert_eq!(name_at(11), Some("IDs"));
+        assert_eq!(name_at(12), Some("IDs"));
+        assert_eq!(name_at(15), Some("EmptyGroup"));
+        assert_eq!(name_at(0), None);
+        assert_eq!(name_at(16), None);
+
+        let options = ParseOptions {
+            duplicates: DuplicatePolicy::Merge,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options("[A]\nred\n[B]\nblue\n[A]\n# comment\ngreen", options);
+        assert_eq!(parser.groups()[0].spans, [1..3, 5..8]);
+        assert_eq!(parser.group_at_line(7).unwrap().name, "A");
+        assert!(parser.group_at_line(6).is_none());
+    }
+
+    #[test]
+    fn tag_set_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let tags = parser.tag_set();
+        assert_eq!(tags.len(), 4);
+        assert!(tags.contains("進撃の巨人"));
+        assert!(tags.contains("102349"));
+
+        let parser = TagParser::from("[A]\nred\nred\n[B]\nred\nblue");
+        assert_eq!(parser.tag_set(), HashSet::from(["red", "blue"]));
+    }
+
+    #[test]
+    fn write_groups_to_file_test() {
+        let path =
+            std::env::temp_dir().join(format!("tag_parser_subset_{}.txt", std::process::id()));
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+
+        let skipped = parser
+            .write_groups_to_file(&["EmptyGroup", "Missing", "Generic"], &path, false)
+            .unwrap();
+        assert_eq!(skipped, vec!["Missing"]);
+
+        let mut written = TagParser::new(&path);
+        written.parse();
+        let groups = written.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "EmptyGroup");
+        assert_eq!(groups[1].name, "Generic");
+        assert_eq!(groups[1].tags, parser.groups()[0].tags);
+
+        let err = parser
+            .write_groups_to_file(&["IDs", "Missing"], &path, true)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn lint_whitespace_test() {
+        let data = "[Generic] \nred_hair  \nsmile\n# comment \ndress\t\n\n[IDs]\n102349";
+        let parser = TagParser::from(data);
+        assert_eq!(parser.lint_whitespace(), vec![2, 5]);
+        assert_eq!(parser.groups()[0].tags, vec!["red_hair", "smile", "dress"]);
+
+        let data = include_str!("test_data.txt");
+        assert!(TagParser::from(data).lint_whitespace().is_empty());
+    }
+
+    #[test]
+    fn compact_format_test() {
+        let options = ParseOptions {
+            compact_format: Some(CompactFormat::default()),
+            ..Default::default()
+        };
+        let parser =
+            TagParser::with_options("Colors: red blue  green\nShapes:circle # round", options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "Colors");
+        assert_eq!(groups[0].tags, vec!["red", "blue", "green"]);
+        assert_eq!(groups[1].name, "Shapes");
+        assert_eq!(groups[1].tags, vec!["circle"]);
+
+        let options = ParseOptions {
+            compact_format: Some(CompactFormat {
+                name_separator: '=',
+                tag_separators: vec![','],
+            }),
+            ..Default::default()
+        };
+        let data = "[Generic]\nred_hair female\nHair = red hair, blue hair\nstray\n\n[IDs]\n102349";
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags, vec!["red_hair female"]);
+        assert_eq!(groups[1].name, "Hair");
+        assert_eq!(groups[1].tags, vec!["red hair", "blue hair"]);
+        assert_eq!(groups[2].name, "IDs");
+        assert_eq!(parser.skipped()[0].reason, SkipReason::Orphan);
+
+        let parser = TagParser::from("[Generic]\nColors: red");
+        assert_eq!(parser.groups()[0].tags, vec!["Colors: red"]);
+
+        // Tags that only look like compact lines stay in their bracket group
+        let options = ParseOptions {
+            compact_format: Some(CompactFormat::default()),
+            ..Default::default()
+        };
+        let data =
+            "[Links]\nhttp://x\nnote:tag # no space\nfoo/bar: baz\nColors: red\nstray\nmailto:me";
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].name, "Links");
+        assert_eq!(groups[0].tags, vec!["http://x", "note:tag", "foo/bar: baz"]);
+        assert_eq!(groups[1].name, "Colors");
+        assert_eq!(groups[1].tags, vec!["red"]);
+        assert_eq!(groups[2].name, "mailto");
+        assert_eq!(groups[2].tags, vec!["me"]);
+        assert_eq!(parser.skipped()[0].text, "stray");
+    }
+
+    #[test]
+    fn sort_with_pins_test() {
+        let mut group = Group {
+            name: "Generic".to_string(),
+            tags: ["fire", "smile", "dress", "red_hair", "apple", "dancing"]
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect(),
+            ..Default::default()
+        };
+
+        group.sort_with_pins(&["smile", "missing", "fire"]);
+        assert_eq!(
+            group.tags,
+            vec!["smile", "fire", "apple", "dancing", "dress", "red_hair"]
+        );
+    }
+
+    #[test]
+    fn similarity_test() {
+        let group = |name: &str, tags: &[&str]| Group {
+            name: name.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let a = group("A", &["red", "blue", "green", "red"]);
+        let b = group("B", &["blue", "green", "yellow", "black"]);
+        let c = group("C", &["smile"]);
+        assert_eq!(a.similarity(&b), 2.0 / 5.0);
+        assert_eq!(b.similarity(&a), 2.0 / 5.0);
+        assert_eq!(a.similarity(&a), 1.0);
+        assert_eq!(a.similarity(&c), 0.0);
+        assert_eq!(group("E", &[]).similarity(&group("F", &[])), 1.0);
+
+        let data =
+            "[A]\nred\nblue\ngreen\n[B]\nblue\ngreen\nyellow\nblack\n[C]\nred\nblue\n[D]\nsmile";
+        let parser = TagParser::from(data);
+        assert_eq!(parser.most_similar_to("A").unwrap().name, "C");
+        assert_eq!(parser.most_similar_to("B").unwrap().name, "A");
+        assert_eq!(parser.most_similar_to("D").unwrap().name, "A");
+        assert!(parser.most_similar_to("Missing").is_none());
+        assert!(TagParser::from("[A]\nred").most_similar_to("A").is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn header_regex_test() {
+        let options = ParseOptions {
+            header_regex: Some(regex::Regex::new(r"^## Group: (.+) ##$").unwrap()),
+            ..Default::default()
+        };
+        let data =
+            "## Group: Generic ##\nred_hair\n# comment\n[not a header]\n## Group: IDs ##\n102349";
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags, vec!["red_hair", "[not a header]"]);
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[1].tags, vec!["102349"]);
+        assert_eq!(parser.stats().comment_count, 1);
+    }
+
+    #[test]
+    fn flatten_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let group = parser.flatten("Everything", false);
+        assert_eq!(group.name, "Everything");
+        assert_eq!(
+            group.tags,
+            vec![
+                "red_hair female dress",
+                "dancing fire smile",
+                "進撃の巨人",
+                "102349"
+            ]
+        );
+
+        let parser = TagParser::from("[A]\nred\nblue\n[B]\nblue\ngreen\nred");
+        assert_eq!(parser.flatten("All", false).tags.len(), 5);
+        assert_eq!(
+            parser.flatten("All", true).tags,
+            vec!["red", "blue", "green"]
+        );
+    }
+
+    #[test]
+    fn max_groups_test() {
+        let data = include_str!("test_data.txt");
+        let options = ParseOptions {
+            max_groups: Some(2),
+            ..Default::default()
+        };
+        let mut parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[1].tags, vec!["102349"]);
+        assert!(parser.error().is_none());
+        assert_eq!(parser.try_parse(), Ok(()));
+
+        let options = ParseOptions {
+            max_groups: Some(1),
+            on_max_groups: LimitPolicy::Error,
+            ..Default::default()
+        };
+        let mut parser = TagParser::with_options(data, options);
+        assert_eq!(parser.groups().len(), 1);
+        assert_eq!(parser.error(), Some(&ParseError::TooManyGroups { max: 1 }));
+        assert_eq!(
+            parser.try_parse(),
+            Err(ParseError::TooManyGroups { max: 1 })
+        );
+
+        let options = ParseOptions {
+            duplicates: DuplicatePolicy::Merge,
+            max_groups: Some(1),
+            on_max_groups: LimitPolicy::Error,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options("[A]\nred\n[A]\nblue", options);
+        assert!(parser.error().is_none());
+        assert_eq!(parser.groups()[0].tags, vec!["red", "blue"]);
+    }
+
+    #[test]
+    fn unique_tags_of_test() {
+        let data = "[Generic]\nred_hair\nsmile\nred_hair\n[Other]\nsmile\nfire\n[Generic]\nfire";
+        let parser = TagParser::from(data);
+        assert_eq!(parser.unique_tags_of("Generic"), vec!["red_hair"]);
+        assert!(parser.unique_tags_of("Other").is_empty());
+        assert!(parser.unique_tags_of("Missing").is_empty());
+    }
+
+    #[test]
+    fn collect_into_test() {
+        let mut map = HashMap::new();
+        let data = include_str!("test_data.txt");
+        TagParser::from(data).collect_into(&mut map);
+        TagParser::from("[IDs]\n555\n[Extra]\nfire").collect_into(&mut map);
+
+        assert_eq!(map.len(), 4);
+        assert_eq!(map["Generic"].len(), 3);
+        assert_eq!(map["IDs"], vec!["102349", "555"]);
+        assert!(map["EmptyGroup"].is_empty());
+        assert_eq!(map["Extra"], vec!["fire"]);
+    }
+
+    #[test]
+    fn by_namespace_test() {
+        let group = Group {
+            name: "Scene".to_string(),
+            tags: [
+                "char:alice",
+                "loc:forest",
+                "smile",
+                "char:bob",
+                "time:12:30",
+            ]
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect(),
+            ..Default::default()
+        };
+
+        let namespaces = group.by_namespace(':');
+        assert_eq!(namespaces.len(), 4);
+        assert_eq!(namespaces["char"], vec!["alice", "bob"]);
+        assert_eq!(namespaces["loc"], vec!["forest"]);
+        assert_eq!(namespaces["time"], vec!["12:30"]);
+        assert_eq!(namespaces[""], vec!["smile"]);
+    }
+
+    #[test]
+    fn duplicate_group_names_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert!(!parser.has_duplicate_groups());
+        assert!(parser.duplicate_group_names().is_empty());
+
+        let parser = TagParser::from("[IDs]\n1\n[Generic]\nred\n[IDs]\n2\n[Generic]\n[IDs]");
+        assert!(parser.has_duplicate_groups());
+        assert_eq!(parser.duplicate_group_names(), vec!["IDs", "Generic"]);
+    }
+
+    #[test]
+    fn merge_ignore_case_test() {
+        let data = "[Generic]\nred_hair\n[IDs]\n102349\n[generic]\nsmile\n[GENERIC]\nfire";
+        let options = ParseOptions {
+            duplicates: DuplicatePolicy::Merge,
+            merge_ignore_case: true,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags, vec!["red_hair", "smile", "fire"]);
+
+        let options = ParseOptions {
+            duplicates: DuplicatePolicy::Merge,
+            ..Default::default()
+        };
+        assert_eq!(TagParser::with_options(data, options).groups().len(), 4);
+    }
+
+    #[test]
+    fn count_tags_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let is_numeric = |tag: &str| !tag.is_empty() && tag.chars().all(|c| c.is_ascii_digit());
+        assert_eq!(parser.count_tags(is_numeric), 1);
+        assert_eq!(parser.count_tags(|_| true), 4);
+        assert_eq!(parser.count_tags(|tag| tag.contains("fire")), 1);
+    }
+
+    #[test]
+    fn description_test() {
+        let data = "[Generic]\n# Common visual tags\nred_hair\n# not a description\n[IDs] # trailing\n102349\n# too late";
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups[0].description.as_deref(), Some("Common visual tags"));
+        assert_eq!(groups[0].tags, vec!["red_hair"]);
+        assert_eq!(groups[1].description, None);
+        assert_eq!(parser.stats().comment_count, 3);
+        assert!(TagParser::round_trip_equal(
+            "[Generic]\n# Common visual tags\nred_hair"
+        ));
+
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert!(parser
+            .groups()
+            .iter()
+            .all(|group| group.description.is_none()));
+    }
+
+    #[test]
+    fn groups_by_size_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let names = |groups: Vec<&Group>| -> Vec<String> {
+            groups.iter().map(|group| group.name.clone()).collect()
+        };
+
+        assert_eq!(
+            names(parser.groups_by_size(true)),
+            vec!["Generic", "IDs", "EmptyGroup"]
+        );
+        assert_eq!(
+            names(parser.groups_by_size(false)),
+            vec!["EmptyGroup", "IDs", "Generic"]
+        );
+
+        let parser = TagParser::from("[A]\nred\n[B]\n1\n2\n[C]\nblue");
+        assert_eq!(names(parser.groups_by_size(true)), vec!["B", "A", "C"]);
+        assert_eq!(names(parser.groups_by_size(false)), vec!["A", "C", "B"]);
+    }
+
+    #[test]
+    fn indented_tags_test() {
+        let options = ParseOptions {
+            indented_tags: true,
+            ..Default::default()
+        };
+
+        let data = "[Colors]\n    red\n    blue\nstray\n[Numbers]\n\t1\n\t2";
+        let parser = TagParser::with_options(data, options.clone());
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].tags, vec!["red", "blue"]);
+        assert_eq!(groups[0].spans, vec![1..4]);
+        assert_eq!(groups[1].tags, vec!["1", "2"]);
+        assert_eq!(parser.stats().orphan_count, 1);
+
+        // A line with less indentation than the first tag ends the group
+        let data = "[Colors]\n    red\n  blue\n    green\n[Numbers]\n  1";
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups[0].tags, vec!["red"]);
+        assert_eq!(groups[1].tags, vec!["1"]);
+        assert_eq!(parser.stats().orphan_count, 2);
+
+        let parser = TagParser::from("[Colors]\n    red\nblue");
+        assert_eq!(parser.groups()[0].tags, vec!["red", "blue"]);
+    }
+
+    #[test]
+    fn iter_mut_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        for group in parser.iter_mut() {
+            for tag in group.tags.iter_mut() {
+                *tag = tag.to_uppercase();
+            }
+        }
+
+        let groups = parser.groups();
+        assert_eq!(
+            groups[0].tags,
+            vec!["RED_HAIR FEMALE DRESS", "DANCING FIRE SMILE", "進撃の巨人"]
+        );
+        assert_eq!(groups[1].tags, vec!["102349"]);
+        assert!(groups[2].tags.is_empty());
+    }
+
+    #[test]
+    fn tab_separators_test() {
+        let options = ParseOptions {
+            compact_format: Some(CompactFormat {
+                tag_separators: vec!['\t'],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let data = "Hair: red hair\tlong blue hair\t\tshort\nEyes:green eyes";
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups[0].tags, vec!["red hair", "long blue hair", "short"]);
+        assert_eq!(groups[1].tags, vec!["green eyes"]);
+
+        let options = ParseOptions {
+            compact_format: Some(CompactFormat::default()),
+            ..Default::default()
+        };
+        let parser = TagParser::with_options("Hair: red hair\tshort", options);
+        assert_eq!(parser.groups()[0].tags, vec!["red", "hair", "short"]);
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn to_toml_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert_eq!(
+            parser.to_toml(),
+            "[Generic]\n\
+             tags = [\"red_hair female dress\", \"dancing fire smile\", \"進撃の巨人\"]\n\
+             \n\
+             [IDs]\n\
+             tags = [\"102349\"]\n\
+             \n\
+             [EmptyGroup]\n\
+             tags = []\n"
+        );
+
+        let parser = TagParser::from("[Say \"hi\"|hi]\nback\\slash\n[a.b]\n[-_]");
+        assert_eq!(
+            parser.to_toml(),
+            "[\"Say \\\"hi\\\"\"]\ntags = [\"back\\\\slash\"]\n\n[\"a.b\"]\ntags = []\n\n[-_]\ntags = []\n"
+        );
+        assert_eq!(TagParser::from("").to_toml(), "");
+    }
+
+    #[test]
+    fn preview_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let preview = parser.preview(2);
+
+        assert_eq!(preview.len(), 3);
+        assert_eq!(preview[0].0, "Generic");
+        assert_eq!(
+            preview[0].1,
+            ["red_hair female dress", "dancing fire smile"]
+        );
+        assert_eq!(preview[1].1, ["102349"]);
+        assert!(preview[2].1.is_empty());
+        assert!(parser.preview(0).iter().all(|(_, tags)| tags.is_empty()));
+    }
+
+    #[test]
+    fn resolve_includes_test() {
+        let data = "[Hair|H]\nred_hair\n[Outfit]\ndress\n@H\n[Full]\n@Outfit\nsmile\n@missing";
+        let mut parser = TagParser::from(data);
+        parser.record_history(true);
+        assert_eq!(parser.resolve_includes(), Ok(()));
+
+        let groups = parser.groups();
+        assert_eq!(groups[0].tags, vec!["red_hair"]);
+        assert_eq!(groups[1].tags, vec!["dress", "red_hair"]);
+        assert_eq!(
+            groups[2].tags,
+            vec!["dress", "red_hair", "smile", "@missing"]
+        );
+
+        assert!(parser.undo());
+        assert_eq!(
+            parser.groups()[2].tags,
+            vec!["@Outfit", "smile", "@missing"]
+        );
+
+        let data = "[A]\nred\n@B\n[B]\nblue\n@C\n[C]\n@A\n[D]\n@D";
+        let mut parser = TagParser::from(data);
+        let error = parser.resolve_includes().unwrap_err();
+        assert_eq!(
+            error,
+            ParseError::IncludeCycle {
+                group: "A".to_string()
+            }
+        );
+        assert_eq!(parser.groups()[0].tags, vec!["red", "@B"]);
+
+        let mut parser = TagParser::from("[D]\n@D");
+        assert!(parser.resolve_includes().is_err());
+    }
+
+    #[test]
+    fn is_clean_test() {
+        let parser = TagParser::from("# Colors\n[Colors]\nred\n\n[Empty]");
+        assert!(parser.is_clean());
+
+        let data = include_str!("test_data.txt");
+        assert!(!TagParser::from(data).is_clean());
+
+        let options = ParseOptions {
+            max_groups: Some(1),
+            on_max_groups: LimitPolicy::Error,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options("[A]\nred\n[B]\nblue", options);
+        assert!(parser.error().is_some());
+        assert!(!parser.is_clean());
+
+        let mut parser = TagParser::from("[Colors]\nred");
+        parser.parse_additional("[Shapes]\ncircle");
+        assert!(parser.is_clean());
+        parser.parse_additional("stray");
+        assert!(!parser.is_clean());
+    }
+
+    #[test]
+    fn to_markdown_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let markdown = parser.to_markdown();
+
+        assert_eq!(
+            markdown,
+            "## Generic\n\
+             \n\
+             - red_hair female dress\n\
+             - dancing fire smile\n\
+             - 進撃の巨人\n\
+             \n\
+             ## IDs\n\
+             \n\
+             - 102349\n\
+             \n\
+             ## EmptyGroup\n\
+             \n\
+             *(no tags)*\n"
+        );
+        assert_eq!(
+            markdown
+                .lines()
+                .filter(|line| line.starts_with("## "))
+                .count(),
+            3
+        );
+        assert_eq!(TagParser::from("").to_markdown(), "");
+    }
+
+    #[test]
+    fn partition_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let (large, small) = parser.partition(|group| group.tags.len() >= 2);
+
+        assert_eq!(large.groups().len(), 1);
+        assert_eq!(large.groups()[0].name, "Generic");
+        assert_eq!(large.groups()[0].tags.len(), 3);
+        assert!(large.groups()[0].spans.is_empty());
+
+        let names: Vec<&str> = small
+            .groups()
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["IDs", "EmptyGroup"]);
+
+        // The new data parses back into the same groups
+        let mut small = small;
+        small.parse();
+        assert_eq!(small.groups()[0].tags, vec!["102349"]);
+        assert_eq!(small.groups()[1].spans, vec![4..5]);
+        assert!(small.is_clean());
+    }
+
+    #[test]
+    fn lenient_headers_test() {
+        let data = "[Generic\nred_hair\n[IDs] # Numbers\n102349\n[Colors|Hues # Open\nred";
+        let mut parser = TagParser::from(data);
+        assert_eq!(
+            parser.try_parse(),
+            Err(ParseError::UnclosedHeader { line: 1 })
+        );
+        assert!(parser.groups().is_empty());
+        assert!(!parser.is_clean());
+
+        let options = ParseOptions {
+            lenient_headers: true,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(parser.error(), None);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags, vec!["red_hair"]);
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[2].name, "Colors");
+        assert_eq!(groups[2].aliases, vec!["Hues"]);
+
+        let parser = TagParser::from("[Generic]\nred_hair\n[IDs\n102349");
+        assert_eq!(
+            parser.error(),
+            Some(&ParseError::UnclosedHeader { line: 3 })
+        );
+        assert_eq!(parser.groups()[0].tags, vec!["red_hair"]);
+    }
+
+    #[test]
+    fn joined_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert_eq!(
+            parser.groups()[0].joined(" "),
+            "red_hair female dress dancing fire smile 進撃の巨人"
+        );
+        assert_eq!(parser.groups()[2].joined(" "), "");
+
+        let joined = parser.joined_tags(" ");
+        assert_eq!(joined.len(), 3);
+        assert_eq!(joined["Generic"], parser.groups()[0].joined(" "));
+        assert_eq!(joined["IDs"], "102349");
+
+        let parser = TagParser::from("[A]\nred\n[A]\nblue");
+        assert_eq!(parser.joined_tags(",")["A"], "red,blue");
+    }
+
+    #[test]
+    fn parse_prefix_test() {
+        let data = "[Colors]\nred\nblue\n\n[Shapes]\ncircle\n[Sizes]\nlarge";
+        let mut parser = TagParser::from(data);
+
+        // The limit falls inside the `[Shapes]` header, so only the first group is read
+        parser.parse_prefix(22);
+        assert_eq!(parser.groups().len(), 1);
+        assert_eq!(parser.groups()[0].name, "Colors");
+        assert_eq!(parser.groups()[0].tags, vec!["red", "blue"]);
+
+        parser.parse_prefix(5);
+        assert!(parser.groups().is_empty());
+
+        parser.parse_prefix(data.len());
+        assert_eq!(parser.groups().len(), 3);
+        parser.parse_prefix(usize::MAX);
+        assert_eq!(parser.groups()[2].tags, vec!["large"]);
+    }
+
+    #[test]
+    fn detect_format_test() {
+        let data = include_str!("test_data.txt");
+        let hint = TagParser::detect_format(data);
+        assert_eq!(
+            hint,
+            FormatHint {
+                style: FormatStyle::Brackets,
+                comment_prefix: Some("#"),
+                tag_separator: None,
+            }
+        );
+        let options = hint.to_options();
+        assert!(options.compact_format.is_none() && !options.indented_tags);
+
+        let data = "Colors: red blue\nShapes: circle square\n\nSizes: large";
+        let hint = TagParser::detect_format(data);
+        assert_eq!(hint.style, FormatStyle::Compact);
+        assert_eq!(hint.comment_prefix, None);
+        assert_eq!(hint.tag_separator, None);
+        let parser = TagParser::with_options(data, hint.to_options());
+        assert_eq!(parser.groups().len(), 3);
+        assert_eq!(parser.groups()[1].tags, vec!["circle", "square"]);
+
+        let data = "Hair: red hair\tlong hair\nEyes: blue eyes";
+        assert_eq!(TagParser::detect_format(data).tag_separator, Some('\t'));
+
+        let data = "; Tags\n[Colors]\n  red\n  blue\n[Shapes]\n  circle";
+        let hint = TagParser::detect_format(data);
+        assert_eq!(hint.style, FormatStyle::Indented);
+        assert_eq!(hint.comment_prefix, Some(";"));
+        assert!(hint.to_options().indented_tags);
+
+        assert_eq!(TagParser::detect_format("").style, FormatStyle::Brackets);
+    }
+
+    #[test]
+    fn expand_synonyms_test() {
+        let mut parser =
+            TagParser::from("[Colors]\nred\nscarlet\n[Shapes]\ncircle\n[Mixed]\nred\nround");
+        parser.record_history(true);
+        let map = HashMap::from([
+            (
+                "red".to_string(),
+                vec!["crimson".to_string(), "scarlet".to_string()],
+            ),
+            (
+                "round".to_string(),
+                vec!["circle".to_string(), "crimson".to_string()],
+            ),
+        ]);
+        parser.expand_synonyms(&map);
+
+        let groups = parser.groups();
+        assert_eq!(groups[0].tags, vec!["red", "scarlet", "crimson"]);
+        assert_eq!(groups[1].tags, vec!["circle"]);
+        assert_eq!(
+            groups[2].tags,
+            vec!["red", "round", "crimson", "scarlet", "circle"]
+        );
+
+        assert!(parser.undo());
+        assert_eq!(parser.groups()[2].tags, vec!["red", "round"]);
+    }
+
+    #[test]
+    fn tags_missing_from_test() {
+        let data = include_str!("test_data.txt");
+        let source = TagParser::from(data);
+        let target = TagParser::from(
+            "[Generic]\nred_hair female dress\n進撃の巨人\n[Generic]\ndancing fire smile\n[Other]\n102349",
+        );
+        assert_eq!(
+            source.tags_missing_from(&target),
+            vec![("IDs".to_string(), "102349".to_string())]
+        );
+
+        let target = TagParser::from("[Generic]\nred_hair female dress");
+        let missing = source.tags_missing_from(&target);
+        assert_eq!(missing.len(), 3);
+        assert_eq!(
+            missing[0],
+            ("Generic".to_string(), "dancing fire smile".to_string())
+        );
+        assert!(source.tags_missing_from(&source).is_empty());
+
+        let source = TagParser::from("[A]\nred\nred");
+        assert_eq!(source.tags_missing_from(&TagParser::from("")).len(), 1);
+    }
+
+    #[test]
+    fn header_continuation_test() {
+        let data = "[Very Long \\\n  Name|Short]\n# Wrapped\nred_hair\n[IDs]\n102349";
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "Very Long Name");
+        assert_eq!(groups[0].aliases, vec!["Short"]);
+        assert_eq!(groups[0].description.as_deref(), Some("Wrapped"));
+        assert_eq!(groups[0].tags, vec!["red_hair"]);
+        assert_eq!(groups[0].spans, vec![1..5]);
+        assert_eq!(parser.group_at_line(2).unwrap().name, "Very Long Name");
+        assert_eq!(groups[1].name, "IDs");
+
+        let mut parser = TagParser::from("[A \\\nB \\\nC]\nred");
+        assert_eq!(parser.groups()[0].name, "A B C");
+        assert!(parser.reparse_line(1, "D \\"));
+        assert_eq!(parser.groups()[0].name, "A D C");
+
+        // A backslash in a comment, or after the closing bracket, does not continue the header
+        let parser = TagParser::from("[A] # C:\\\nred\n[B]\nblue");
+        assert_eq!(parser.groups()[0].name, "A");
+        assert_eq!(parser.groups()[0].tags, vec!["red"]);
+        assert_eq!(parser.groups()[1].tags, vec!["blue"]);
+        let options = ParseOptions {
+            lenient_headers: true,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options("[A] x \\\nred", options);
+        assert_eq!(parser.groups()[0].tags, vec!["red"]);
+
+        let parser = TagParser::from("[Wrapped \\ # note\nName]\nred");
+        assert_eq!(parser.groups()[0].name, "Wrapped Name");
+
+        let parser = TagParser::from("[Open \\");
+        assert_eq!(
+            parser.error(),
+            Some(&ParseError::UnclosedHeader { line: 1 })
+        );
+    }
+
+    #[test]
+    fn set_tags_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        parser.record_history(true);
+
+        let tags = vec!["blue_eyes".to_string(), "smile".to_string()];
+        assert!(parser.set_tags("Generic", tags.clone()));
+        assert_eq!(parser.groups()[0].tags, tags);
+        assert_eq!(parser.groups().len(), 3);
+
+        assert!(!parser.set_tags("Colors", vec!["red".to_string()]));
+        assert_eq!(parser.groups().len(), 4);
+        assert_eq!(parser.get_group("Colors").unwrap().tags, vec!["red"]);
+
+        assert!(parser.undo());
+        assert!(parser.get_group("Colors").is_none());
+        assert!(parser.undo());
+        assert_eq!(parser.groups()[0].tags.len(), 3);
+    }
+
+    #[test]
+    fn events_test() {
+        let data = include_str!("test_data.txt");
+        let events: Vec<Event> = TagParser::events(data).collect();
+        assert_eq!(events.len(), data.lines().count());
+        assert_eq!(
+            events[..8],
+            [
+                Event::Comment("This is a list of generic tags".to_string(), 1),
+                Event::Blank(2),
+                Event::Tag("orphan_tag".to_string(), 3),
+                Event::Blank(4),
+                Event::GroupStart("Generic".to_string(), 5),
+                Event::Tag("red_hair female dress".to_string(), 6),
+                Event::Tag("dancing fire smile".to_string(), 7),
+                Event::Tag("進撃の巨人".to_string(), 8),
+            ]
+        );
+        assert_eq!(events[10], Event::GroupStart("IDs".to_string(), 11));
+        assert_eq!(events[11], Event::Tag("102349".to_string(), 12));
+
+        let starts: Vec<&Event> = events
+            .iter()
+            .filter(|event| matches!(event, Event::GroupStart(..)))
+            .collect();
+        assert_eq!(starts.len(), TagParser::from(data).groups().len());
+    }
+
+    #[test]
+    fn validate_against_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let mut allowed: HashSet<&str> =
+            HashSet::from(["red_hair female dress", "進撃の巨人", "102349"]);
+
+        assert_eq!(
+            parser.validate_against(&allowed),
+            vec![("Generic".to_string(), "dancing fire smile".to_string())]
+        );
+
+        allowed.insert("dancing fire smile");
+        assert!(parser.validate_against(&allowed).is_empty());
+        assert_eq!(parser.validate_against(&HashSet::new()).len(), 4);
+    }
+
+    #[test]
+    fn priority_test() {
+        let data = "[A]\nred\n[B]\n# Shapes\n# priority: 2\ncircle\n[C]\n# priority: -1\n# Sizes\n\
+                    [D]\n\n# priority: 0\n[E]\n# priority: high\n[F]\n# priority: 2";
+        let mut parser = TagParser::from(data);
+        let groups = parser.groups();
+        assert_eq!(groups[1].priority, Some(2));
+        assert_eq!(groups[1].description.as_deref(), Some("Shapes"));
+        assert_eq!(groups[2].priority, Some(-1));
+        assert_eq!(groups[2].description.as_deref(), Some("Sizes"));
+        assert_eq!(groups[3].priority, None);
+        assert_eq!(groups[4].priority, None);
+        assert_eq!(groups[4].description.as_deref(), Some("priority: high"));
+
+        parser.sort_by_priority();
+        let names: Vec<&str> = parser
+            .groups()
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["C", "B", "F", "A", "D", "E"]);
+
+        let formatted = parser.to_string();
+        assert!(formatted.starts_with("[C]\n# Sizes\n# priority: -1\n\n[B]\n"));
+        let reparsed = TagParser::from(formatted.as_str());
+        let priorities: Vec<Option<i32>> = reparsed
+            .groups()
+            .iter()
+            .map(|group| group.priority)
+            .collect();
+        assert_eq!(
+            priorities,
+            vec![Some(-1), Some(2), Some(2), None, None, None]
+        );
+    }
+
+    #[test]
+    fn conflicting_groups_test() {
+        let data = "[Colors]\nred\nblue\n[Shapes]\ncircle\n[Colors]\nred\ngreen\n\
+                    [Shapes]\ncircle\ncircle\n[Sizes]\nlarge\n[Sizes]\n[Sizes]\nlarge";
+        let parser = TagParser::from(data);
+        assert_eq!(
+            parser.duplicate_group_names(),
+            vec!["Colors", "Shapes", "Sizes"]
+        );
+        assert_eq!(parser.conflicting_groups(), vec!["Colors", "Sizes"]);
+
+        let data = include_str!("test_data.txt");
+        assert!(TagParser::from(data).conflicting_groups().is_empty());
+    }
+
+    #[test]
+    fn raw_block_test() {
+        let options = ParseOptions {
+            raw_blocks: true,
+            ..Default::default()
+        };
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::with_options(data, options);
+        let raw = "[Generic] # Test\nred_hair female dress\ndancing fire smile\n進撃の巨人\n";
+        assert_eq!(parser.groups()[0].raw_block.as_deref(), Some(raw));
+        assert_eq!(parser.format_group(&parser.groups()[0]), raw);
+        assert!(!parser.groups()[0].to_string().contains("# Test"));
+
+        parser.add_tag("IDs", "555");
+        let formatted = parser.to_string();
+        assert!(formatted.starts_with(raw));
+        assert!(formatted.contains("[IDs]\n102349\n555\n"));
+
+        let raw = TagParser::from(data).groups()[0].raw_block.clone();
+        assert_eq!(raw, None);
+    }
+
+    #[test]
+    fn tag_group_count_test() {
+        let parser =
+            TagParser::from("[Colors]\nred\nblue\nred\n[Mixed]\nred\ncircle\n[Shapes]\ncircle");
+        assert_eq!(parser.tag_group_count("red"), 2);
+        assert_eq!(parser.tag_group_count("circle"), 2);
+        assert_eq!(parser.tag_group_count("blue"), 1);
+        assert_eq!(parser.tag_group_count("green"), 0);
+    }
+
+    #[test]
+    fn section_break_test() {
+        let data = "[Colors]\nred\n# --- warm ---\nstray\n[Colors]\norange\n[Colors]\nyellow\n# ---\n[Colors]\nblue";
+        let options = ParseOptions {
+            duplicates: DuplicatePolicy::Merge,
+            section_break: Some("---".to_string()),
+            ..Default::default()
+        };
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].tags, vec!["red"]);
+        assert_eq!(groups[1].tags, vec!["orange", "yellow"]);
+        assert_eq!(groups[2].tags, vec!["blue"]);
+        assert_eq!(parser.stats().orphan_count, 1);
+
+        let options = ParseOptions {
+            duplicates: DuplicatePolicy::Merge,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options(data, options);
+        assert_eq!(parser.groups().len(), 1);
+        assert_eq!(
+            parser.groups()[0].tags,
+            vec!["red", "stray", "orange", "yellow", "blue"]
+        );
+    }
+
+    #[test]
+    fn as_slice_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let groups = parser.as_slice();
+        assert_eq!(groups, parser.groups().as_slice());
+
+        let mut tag_count = 0;
+        for group in groups {
+            tag_count += group.tags.len();
+        }
+        assert_eq!(tag_count, parser.stats().tag_count);
+        assert!(
+            matches!(groups, [first, .., last] if first.name == "Generic" && last.name == "EmptyGroup")
+        );
+    }
+
+    #[test]
+    fn header_policy_test() {
+        let data = "[Generic]\nred_hair\n[maybe so]\n[IDs]\n# Numbers\n102349\n[Empty]";
+        let with_policy = |header_policy| {
+            let options = ParseOptions {
+                header_policy,
+                ..Default::default()
+            };
+            TagParser::with_options(data, options)
+        };
+
+        let parser = with_policy(HeaderPolicy::AlwaysHeader);
+        let names: Vec<&str> = parser
+            .groups()
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Generic", "maybe so", "IDs", "Empty"]);
+
+        let parser = with_policy(HeaderPolicy::RequireTagsLineBelow);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].tags, vec!["red_hair", "[maybe so]"]);
+        assert_eq!(groups[1].name, "IDs");
+        assert_eq!(groups[1].tags, vec!["102349", "[Empty]"]);
+
+        let parser = with_policy(HeaderPolicy::HeuristicByContent);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].tags, vec!["red_hair", "[maybe so]"]);
+        assert_eq!(groups[2].name, "Empty");
+    }
+
+    #[test]
+    fn classify_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        parser.add_tag("IDs", "dancing fire smile");
+
+        let classified = parser.classify(&[
+            "dancing fire smile",
+            "unknown",
+            "102349",
+            "進撃の巨人",
+            "unknown",
+            "also unknown",
+        ]);
+        assert_eq!(classified.len(), 3);
+        assert_eq!(
+            classified["Generic"],
+            vec!["dancing fire smile", "進撃の巨人"]
+        );
+        assert_eq!(classified["IDs"], vec!["dancing fire smile", "102349"]);
+        assert_eq!(classified[""], vec!["unknown", "also unknown"]);
+        assert!(parser.classify(&[]).is_empty());
+    }
+
+    #[test]
+    fn common_tags_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        assert!(parser.common_tags().is_empty());
+
+        parser.add_tag("IDs", "進撃の巨人");
+        parser.add_tag("IDs", "進撃の巨人");
+        assert_eq!(parser.common_tags(), vec!["進撃の巨人"]);
+        assert!(parser.groups()[2].tags.is_empty());
+
+        assert!(TagParser::from("[Empty]").common_tags().is_empty());
+    }
+
+    #[test]
+    fn negative_tags_test() {
+        let options = ParseOptions {
+            negative_prefix: Some("-".to_string()),
+            ..Default::default()
+        };
+        let data = "[Positive]\nred_hair\nsmile\n[Negative]\n-blurry\n-low quality\n[Mixed]\nsmile\n-blurry\nwell-lit";
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+
+        assert_eq!(groups[0].positive_tags, vec!["red_hair", "smile"]);
+        assert!(groups[0].negative_tags.is_empty());
+        assert!(groups[1].positive_tags.is_empty());
+        assert_eq!(groups[1].negative_tags, vec!["blurry", "low quality"]);
+        assert_eq!(groups[2].positive_tags, vec!["smile", "well-lit"]);
+        assert_eq!(groups[2].negative_tags, vec!["blurry"]);
+        assert_eq!(groups[2].tags, vec!["smile", "-blurry", "well-lit"]);
+
+        let parser = TagParser::from(data);
+        assert!(parser.groups()[2].negative_tags.is_empty());
+        assert!(parser.groups()[2].positive_tags.is_empty());
+    }
+
+    #[test]
+    fn truncate_groups_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        parser.record_history(true);
+        parser.truncate_groups(2);
+
+        let groups = parser.groups();
+        assert_eq!(
+            groups[0].tags,
+            vec!["red_hair female dress", "dancing fire smile"]
+        );
+        assert_eq!(groups[1].tags, vec!["102349"]);
+        assert!(groups[2].tags.is_empty());
+
+        assert!(parser.undo());
+        assert_eq!(parser.groups()[0].tags.len(), 3);
+
+        parser.truncate_groups(0);
+        assert_eq!(parser.stats().tag_count, 0);
+    }
+
+    #[test]
+    fn end_markers_test() {
+        let with_policy = |data, end_markers| {
+            let options = ParseOptions {
+                end_markers,
+                ..Default::default()
+            };
+            TagParser::with_options(data, options)
+        };
+
+        // Matched markers, and a group without one that ends at the next header
+        let data = "[Generic]\nred_hair\n[/Generic]\nstray\n[IDs]\n102349\n[Colors|C]\nred\n[/C]";
+        let parser = with_policy(data, EndMarkerPolicy::Validate);
+        let groups = parser.groups();
+        assert_eq!(parser.error(), None);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].tags, vec!["red_hair"]);
+        assert_eq!(groups[0].spans, vec![1..3]);
+        assert_eq!(groups[1].tags, vec!["102349"]);
+        assert_eq!(groups[2].tags, vec!["red"]);
+        assert_eq!(parser.stats().orphan_count, 1);
+        assert_eq!(parser.skipped()[0].reason, SkipReason::EndMarker);
+
+        // Mismatched markers close the group, or stop parsing when validated
+        let data = "[Generic]\nred_hair\n[/Colors]\nstray\n[IDs]\n102349";
+        let parser = with_policy(data, EndMarkerPolicy::Close);
+        assert_eq!(parser.groups().len(), 2);
+        assert_eq!(parser.groups()[0].tags, vec!["red_hair"]);
+        assert_eq!(parser.stats().orphan_count, 1);
+
+        let parser = with_policy(data, EndMarkerPolicy::Validate);
+        assert_eq!(
+            parser.error(),
+            Some(&ParseError::MismatchedEndMarker { line: 3 })
+        );
+        assert_eq!(parser.groups()[0].tags, vec!["red_hair"]);
+
+        let parser = with_policy("[/Generic]\nred", EndMarkerPolicy::Validate);
+        assert_eq!(
+            parser.error(),
+            Some(&ParseError::MismatchedEndMarker { line: 1 })
+        );
+
+        let parser = TagParser::from(data);
+        assert_eq!(parser.groups()[1].name, "/Colors");
+    }
+
+    #[test]
+    fn tag_len_stats_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let groups = parser.groups();
+
+        // "進撃の巨人" is 5 characters but 15 bytes
+        assert_eq!(
+            groups[0].tag_len_stats(),
+            (5, 21, (21 + 18 + 5) as f64 / 3.0)
+        );
+        assert_eq!(groups[1].tag_len_stats(), (6, 6, 6.0));
+        assert_eq!(groups[2].tag_len_stats(), (0, 0, 0.0));
+        assert_eq!(parser.tag_len_stats(), (5, 21, 50.0 / 4.0));
+        assert_eq!(TagParser::from("").tag_len_stats(), (0, 0, 0.0));
+    }
+
+    #[test]
+    fn line_preprocessor_test() {
+        fn strip_line_number(line: &str) -> Cow<'_, str> {
+            match line.split_once(": ") {
+                Some((number, rest)) if number.chars().all(|c| c.is_ascii_digit()) => {
+                    Cow::Borrowed(rest)
+                }
+                _ => Cow::Borrowed(line),
+            }
+        }
+
+        let options = ParseOptions {
+            line_preprocessor: Some(strip_line_number),
+            ..Default::default()
+        };
+        let data = "1: [Generic]\n2: red_hair\n3: # comment\n4: \n5: [IDs]\n6: 102349\nplain: tag";
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "Generic");
+        assert_eq!(groups[0].tags, vec!["red_hair"]);
+        assert_eq!(groups[1].tags, vec!["102349", "plain: tag"]);
+        assert_eq!(groups[1].spans, vec![5..8]);
+        assert_eq!(parser.stats().comment_count, 1);
+        assert_eq!(parser.skipped()[1].reason, SkipReason::Blank);
+
+        let parser = TagParser::from(data);
+        assert!(parser.groups().is_empty());
+    }
+
+    #[test]
+    fn map_tags_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let upper = parser.map_tags(|tag| tag.to_uppercase());
+
+        assert_eq!(upper.groups().len(), 3);
+        assert_eq!(upper.groups()[0].name, "Generic");
+        assert_eq!(
+            upper.groups()[0].tags,
+            vec!["RED_HAIR FEMALE DRESS", "DANCING FIRE SMILE", "進撃の巨人"]
+        );
+        assert!(upper.groups()[2].tags.is_empty());
+        assert!(upper.to_string().contains("[IDs]\n102349\n"));
+
+        assert_eq!(parser.groups(), TagParser::from(data).groups());
+        assert_eq!(parser.groups()[0].tags[0], "red_hair female dress");
+    }
+
+    #[test]
+    fn filter_by_confidence_test() {
+        let group = Group {
+            name: "Predicted".to_string(),
+            tags: [
+                "red_hair:0.98",
+                "blue_eyes:0.5",
+                "hat:0.1",
+                "smile",
+                "time:high",
+                ":0.2",
+                "ratio:NaN",
+                "clock:12:0.7",
+            ]
+            .map(String::from)
+            .to_vec(),
+            ..Default::default()
+        };
+        let always = ["smile", "time:high", ":0.2", "ratio:NaN"];
+
+        let mut expected = vec!["red_hair", "blue_eyes", "hat"];
+        expected.extend(always);
+        expected.push("clock:12");
+        assert_eq!(group.filter_by_confidence(0.0), expected);
+
+        assert_eq!(
+            group.filter_by_confidence(0.5),
+            vec![
+                "red_hair",
+                "blue_eyes",
+                "smile",
+                "time:high",
+                ":0.2",
+                "ratio:NaN",
+                "clock:12"
+            ]
+        );
+        assert_eq!(
+            group.filter_by_confidence(0.99),
+            vec!["smile", "time:high", ":0.2", "ratio:NaN"]
+        );
+        assert_eq!(group.filter_by_confidence(1.0), always);
+        assert!(group.filter_by_confidence(1.5).is_empty());
+    }
+
+    #[test]
+    fn effectively_empty_groups_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert!(parser.effectively_empty_groups().is_empty());
+        assert_eq!(parser.empty_groups(), vec!["EmptyGroup"]);
+
+        let data = "[Generic]\nred_hair\n[Notes]\n# only a comment\n\n[Empty]\n\n[IDs]\n102349";
+        let parser = TagParser::from(data);
+        assert_eq!(parser.effectively_empty_groups(), vec!["Notes"]);
+        assert_eq!(parser.empty_groups(), vec!["Notes", "Empty"]);
+    }
+
+    #[test]
+    fn group_name_transform_test() {
+        let options = ParseOptions {
+            group_name_transform: Some(|name| name.replace(' ', "_")),
+            lowercase_group_names: true,
+            compact_format: Some(CompactFormat::default()),
+            ..Default::default()
+        };
+        let data = "[  Hair Colors | Hair Hues ]\nred\nEye Colors: blue green";
+        let parser = TagParser::with_options(data, options);
+        let groups = parser.groups();
+        assert_eq!(groups[0].name, "hair_colors");
+        assert_eq!(groups[0].aliases, vec!["hair_hues"]);
+        assert_eq!(groups[0].tags, vec!["red"]);
+        assert_eq!(groups[1].name, "eye_colors");
+        assert!(parser.get_group("hair_hues").is_some());
+
+        let parser = TagParser::from(data);
+        assert_eq!(parser.groups()[0].name, "Hair Colors");
+    }
+
+    #[test]
+    fn split_at_group_test() {
+        let data = include_str!("test_data.txt");
+        let (head, tail) = TagParser::from(data).split_at_group("IDs");
+        assert_eq!(head.groups().len(), 1);
+        assert_eq!(head.groups()[0].name, "Generic");
+        assert_eq!(head.groups()[0].tags.len(), 3);
+
+        let names: Vec<&str> = tail
+            .groups()
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["IDs", "EmptyGroup"]);
+        assert_eq!(tail.groups()[0].tags, vec!["102349"]);
+        assert!(tail.groups()[0].spans.is_empty());
+
+        let (head, tail) = TagParser::from(data).split_at_group("Missing");
+        assert_eq!(head.groups().len(), 3);
+        assert!(tail.groups().is_empty());
+    }
+
+    #[test]
+    fn check_brackets_test() {
+        let data = include_str!("test_data.txt");
+        assert_eq!(TagParser::from(data).check_brackets(), Ok(()));
+
+        let data = "[Generic]\nred_hair\n[[IDs]\n102349\n\\[literal]]\n[EmptyGroup] # [note]";
+        let parser = TagParser::from(data);
+        assert_eq!(parser.check_brackets(), Err(vec![3]));
+    }
+
+    #[test]
+    fn slugged_names_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert_eq!(parser.slugged_names(), vec!["generic", "ids", "emptygroup"]);
+
+        let parser =
+            TagParser::from("[  --Red & Blue__Hair-- ]\n[進撃の巨人]\n[進撃の巨人 2]\n[!!]");
+        let slugs = parser.slugged_names();
+        assert_eq!(slugs[0], "red-blue-hair");
+        assert_eq!(
+            slugs[1],
+            format!("group-{:016x}", 0x3573_bba8_86f8_926f_u64)
+        );
+        assert_eq!(slugs[2], "2");
+        assert!(slugs[3].starts_with("group-"));
+        assert_ne!(slugs[3], slugs[1]);
+        assert_eq!(slugs[3].len(), "group-".len() + 16);
+    }
+
+    #[test]
+    fn group_tag_set_test() {
+        let data = "[Generic]\nred_hair\nfemale\nred_hair\ndress\nfemale\n[EmptyGroup]";
+        let parser = TagParser::from(data);
+        let generic = parser.get_group("Generic").unwrap();
+        assert_eq!(generic.tags.len(), 5);
+        assert_eq!(
+            generic.tag_set(),
+            HashSet::from(["red_hair", "female", "dress"])
+        );
+        assert!(parser.get_group("EmptyGroup").unwrap().tag_set().is_empty());
+    }
+
+    #[test]
+    fn common_prefix_test() {
+        let parser =
+            TagParser::from("[Characters]\nchar_a\nchar_b\nchar_c\n[Jp]\n進撃の巨人\n進撃の\n進む");
+        assert_eq!(parser.groups()[0].common_prefix(), "char_");
+        assert_eq!(parser.groups()[1].common_prefix(), "進");
+
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert_eq!(parser.get_group("Generic").unwrap().common_prefix(), "");
+        assert_eq!(parser.get_group("IDs").unwrap().common_prefix(), "102349");
+        assert_eq!(parser.get_group("EmptyGroup").unwrap().common_prefix(), "");
+    }
+
+    #[test]
+    fn parse_until_test() {
+        let data = include_str!("test_data.txt");
+        let mut calls = 0;
+        let groups = TagParser::parse_until(data.as_bytes(), |_| {
+            calls += 1;
+            true
+        })
+        .unwrap();
+        assert_eq!(calls, 1);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0], TagParser::from(data).groups()[0]);
+
+        let groups = TagParser::parse_until(data.as_bytes(), |_| false).unwrap();
+        assert_eq!(&groups, TagParser::from(data).groups());
+
+        // The reader is not read past the header of the next group
+        let mut reader = io::Cursor::new("[A]\nred\n[B]\nblue\n[C]\ngreen\n");
+        let groups = TagParser::parse_until(&mut reader, |group| group.name == "A").unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(reader.position() < reader.get_ref().len() as u64);
+
+        let error = TagParser::parse_until("[A\nred".as_bytes(), |_| false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn satisfied_groups_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        let have = HashSet::from([
+            "red_hair female dress",
+            "dancing fire smile",
+            "進撃の巨人",
+            "102",
+        ]);
+        assert_eq!(parser.satisfied_groups(&have, false), vec!["Generic"]);
+        assert_eq!(
+            parser.satisfied_groups(&have, true),
+            vec!["Generic", "EmptyGroup"]
+        );
+
+        let have = HashSet::from(["red_hair female dress", "102349"]);
+        assert_eq!(parser.satisfied_groups(&have, false), vec!["IDs"]);
+    }
+
+    #[test]
+    fn skip_front_matter_test() {
+        let options = ParseOptions {
+            skip_front_matter: true,
+            ..Default::default()
+        };
+        let data = "---\ntitle: [Not a group]\n---\n[Generic]\nred_hair";
+        let parser = TagParser::with_options(data, options.clone());
+        assert_eq!(parser.groups().len(), 1);
+        assert_eq!(parser.groups()[0].name, "Generic");
+        assert_eq!(parser.groups()[0].spans, vec![4..6]);
+        let reasons: Vec<SkipReason> = parser.skipped().iter().map(|line| line.reason).collect();
+        assert_eq!(reasons, vec![SkipReason::FrontMatter; 3]);
+
+        let parser = TagParser::with_options("#!/usr/bin/tags\n[IDs]\n102349", options.clone());
+        assert_eq!(parser.groups()[0].tags, vec!["102349"]);
+        assert_eq!(parser.skipped()[0].line, 1);
+        assert_eq!(parser.skipped()[0].reason, SkipReason::FrontMatter);
+
+        let data = "#!/usr/bin/tags\r\n---\r\nkey: value\r\n---\r\n[IDs]\r\n102349";
+        let parser = TagParser::with_options(data, options.clone());
+        assert_eq!(parser.skipped().len(), 4);
+        assert!(parser.is_clean());
+
+        // An unclosed block is not front matter
+        let parser = TagParser::with_options("---\n[IDs]\n102349", options);
+        assert_eq!(parser.groups()[0].tags, vec!["102349"]);
+        assert_eq!(parser.skipped()[0].reason, SkipReason::Orphan);
+
+        let parser = TagParser::from("---\ntitle: x\n---\n[IDs]\n102349");
+        assert_eq!(parser.stats().orphan_count, 3);
+    }
+
+    #[test]
+    fn vocabulary_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        parser.parse_additional("[More]\n102349\nangry");
+        assert_eq!(
+            parser.vocabulary(),
+            vec![
+                "102349",
+                "angry",
+                "dancing fire smile",
+                "red_hair female dress",
+                "進撃の巨人",
+            ]
+        );
+        assert!(TagParser::from("[Empty]").vocabulary().is_empty());
+    }
+
+    #[test]
+    fn nested_groups_test() {
+        let options = ParseOptions {
+            nested_groups: true,
+            ..Default::default()
+        };
+        let data = "[Top]\nred\n[[Sub|Child]] # note\nblue\n[[[SubSub]]]\ngreen\n[Next]";
+        let parser = TagParser::with_options(data, options.clone());
+        let depths: Vec<(&str, usize)> = parser
+            .groups()
+            .iter()
+            .map(|group| (group.name.as_str(), group.depth))
+            .collect();
+        assert_eq!(
+            depths,
+            vec![("Top", 1), ("Sub", 2), ("SubSub", 3), ("Next", 1)]
+        );
+        assert_eq!(parser.groups()[1].aliases, vec!["Child"]);
+        assert_eq!(parser.check_brackets(), Ok(()));
+
+        // Groups are written back with their depth
+        let written = parser.to_string();
+        assert!(written.contains("[[[SubSub]]]\n"));
+        let reparsed = TagParser::with_options(&written, options);
+        for (group, original) in reparsed.groups().iter().zip(parser.groups()) {
+            assert_eq!((&group.name, group.depth), (&original.name, original.depth));
+        }
+
+        let parser = TagParser::from(data);
+        assert_eq!(parser.groups()[1].name, "[Sub");
+        assert_eq!(parser.groups()[2].name, "[[SubSub]]");
+        assert!(parser.groups().iter().all(|group| group.depth == 1));
+    }
+
+    #[test]
+    fn rebuild_data_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        parser.add_tag("EmptyGroup", "new_tag");
+        parser.remove_group("IDs");
+        parser.rename_tag("dancing fire smile", "dancing", false);
+
+        parser.rebuild_data();
+        assert_eq!(parser.data, parser.to_string());
+        assert!(parser.skipped().is_empty());
+        assert!(parser.groups().iter().all(|group| group.spans.is_empty()));
+
+        let groups = parser.groups().clone();
+        parser.parse();
+        assert_eq!(parser.groups().len(), groups.len());
+        for (group, expected) in parser.groups().iter().zip(&groups) {
+            assert_eq!(group.name, expected.name);
+            assert_eq!(group.tags, expected.tags);
+        }
+        assert_eq!(parser.group_at_line(7).unwrap().name, "EmptyGroup");
+        assert!(parser.is_clean());
+    }
+
+    #[test]
+    fn write_preserving_comments_test() {
+        let data = "# Header comment\n\n[Generic]\n# inside Generic\nred_hair\n\n# About IDs\n[IDs]\n102349\n\n# About extra\n[Extra]\nx\n\n# Trailing\n";
+        let mut parser = TagParser::from(data);
+        parser.add_tag("IDs", "555");
+        parser.remove_group("Extra");
+        parser.set_tags("New", vec!["tag".to_string()]);
+
+        assert_eq!(
+            parser.to_string_preserving_comments(),
+            "# Header comment\n\n[Generic]\n# inside Generic\nred_hair\n\n# About IDs\n[IDs]\n102349\n555\n\n[New]\ntag\n\n# About extra\n\n# Trailing\n"
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("tag_parser_preserving_{}.txt", std::process::id()));
+        parser.write_preserving_comments(&path).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, parser.to_string_preserving_comments());
+
+        // Unchanged data is written back exactly
+        let parser = TagParser::from(data);
+        assert_eq!(parser.to_string_preserving_comments(), data);
+    }
+
+    #[test]
+    fn write_preserving_comments_reordered_test() {
+        let data = "# top\n[A]\na\n\n# mid\n[B]\nb\n\n# end\n[C]\nc\n";
+        let mut parser = TagParser::from(data);
+        parser.move_group("B", 0);
+        assert_eq!(
+            parser.to_string_preserving_comments(),
+            "[B]\nb\n\n# top\n[A]\na\n\n# mid\n\n# end\n[C]\nc\n"
+        );
+
+        let mut parser = TagParser::from(data);
+        parser.move_group("A", 2);
+        assert_eq!(
+            parser.to_string_preserving_comments(),
+            "# top\n\n# mid\n[B]\nb\n\n# end\n[C]\nc\n\n[A]\na\n"
+        );
+    }
+
+    #[test]
+    fn write_preserving_comments_options_test() {
+        let options = ParseOptions {
+            lowercase_group_names: true,
+            tag_trim_chars: vec!['*'],
+            ..Default::default()
+        };
+        let data = "[Generic] # keep me\n*red*\n# inside\n*blue*\n\n# About IDs\n[IDs]\n1\n";
+        let mut parser = TagParser::with_options(data, options);
+        assert_eq!(parser.to_string_preserving_comments(), data);
+
+        parser.add_tag("ids", "2");
+        assert_eq!(
+            parser.to_string_preserving_comments(),
+            "[Generic] # keep me\n*red*\n# inside\n*blue*\n\n# About IDs\n[ids]\n1\n2\n"
+        );
+    }
+
+    #[test]
+    fn find_non_ascii_tags_test() {
+        let data = include_str!("test_data.txt");
+        let parser = TagParser::from(data);
+        assert_eq!(
+            parser.find_non_ascii_tags(),
+            vec![("Generic".to_string(), "進撃の巨人".to_string())]
+        );
+        assert!(TagParser::from("[IDs]\n102349\nred_hair")
+            .find_non_ascii_tags()
+            .is_empty());
+    }
+
+    #[test]
+    fn collapse_empty_runs_test() {
+        let data = "[Generic]\nred_hair\n[A]\n[B]\n[C]\n[IDs]\n102349\n[D]";
+        let names = |parser: &TagParser| -> Vec<String> {
+            parser
+                .groups()
+                .iter()
+                .map(|group| group.name.clone())
+                .collect()
+        };
+
+        let mut parser = TagParser::from(data);
+        parser.record_history(true);
+        parser.collapse_empty_runs(false);
+        assert_eq!(names(&parser), vec!["Generic", "A", "IDs", "D"]);
+        assert!(parser.undo());
+        assert_eq!(parser.groups().len(), 6);
+
+        parser.collapse_empty_runs(true);
+        assert_eq!(names(&parser), vec!["Generic", "IDs"]);
+    }
+
+    #[test]
+    fn cooccurrence_test() {
+        let data = include_str!("test_data.txt");
+        let counts = TagParser::from(data).cooccurrence();
+        let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+        assert_eq!(
+            counts,
+            HashMap::from([
+                (pair("dancing fire smile", "red_hair female dress"), 1),
+                (pair("dancing fire smile", "進撃の巨人"), 1),
+                (pair("red_hair female dress", "進撃の巨人"), 1),
+            ])
+        );
+
+        let parser = TagParser::from("[A]\nred\nred\nblue\n[B]\nred\nblue");
+        assert_eq!(
+            parser.cooccurrence(),
+            HashMap::from([(pair("blue", "red"), 2)])
+        );
+    }
+
+    #[test]
+    fn parse_filtered_test() {
+        let data = "[Generic]\nred_hair\n[123]\n102349\n# note\n[_Hidden]\nsecret\n[EmptyGroup]\n";
+        let groups = TagParser::parse_filtered(data.as_bytes(), |name| {
+            name.starts_with(char::is_alphabetic)
+        })
+        .unwrap();
+        let names: Vec<&str> = groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, vec!["Generic", "EmptyGroup"]);
+        assert_eq!(groups[0].tags, vec!["red_hair"]);
+        assert_eq!(groups[1].spans, vec![8..9]);
+
+        // The filter sees the whole name of a continued header
+        let data = "[Skip \\\nMe]\nred\n[Keep]\nblue";
+        let groups = TagParser::parse_filtered(data.as_bytes(), |name| name == "Keep").unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "Keep");
+        assert_eq!(groups[0].tags, vec!["blue"]);
+        let groups = TagParser::parse_filtered(data.as_bytes(), |name| name == "Skip Me").unwrap();
+        assert_eq!(groups[0].tags, vec!["red"]);
+
+        // The filter runs when the header is read, before the lines of the group
+        let names = std::cell::RefCell::new(Vec::new());
+        let data = b"[Keep]\nred\n[Skip]\nblue\n\xff\n";
+        let result = TagParser::parse_filtered(&data[..], |name| {
+            names.borrow_mut().push(name.to_string());
+            name == "Keep"
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(names.into_inner(), vec!["Keep", "Skip"]);
+
+        // Only the groups that pass the filter are built
+        let mut built = Vec::new();
+        let data = "[Keep]\nred\n[Skip]\nblue\n# note\n[Keep]\ngreen";
+        let groups = TagParser::parse_reader(
+            data.as_bytes(),
+            |name| name == "Keep",
+            |group| {
+                built.push(group.name.clone());
+                false
+            },
+        )
+        .unwrap();
+        assert_eq!(built, vec!["Keep", "Keep"]);
+        assert_eq!(groups[1].tags, vec!["green"]);
+        assert_eq!(groups[1].spans, vec![6..8]);
+
+        let data = include_str!("test_data.txt");
+        let groups = TagParser::parse_filtered(data.as_bytes(), |_| true).unwrap();
+        assert_eq!(&groups, TagParser::from(data).groups());
+        assert!(TagParser::parse_filtered(data.as_bytes(), |_| false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn inverted_index_test() {
+        let data = include_str!("test_data.txt");
+        let mut parser = TagParser::from(data);
+        let index = parser.inverted_index();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index["進撃の巨人"], vec!["Generic"]);
+        assert_eq!(index["102349"], vec!["IDs"]);
+
+        parser.parse_additional("[More]\n102349\n102349");
+        assert_eq!(parser.inverted_index()["102349"], vec!["IDs", "More"]);
+    }
+
+    #[test]
+    fn reject_mixed_indentation_test() {
+        let options = ParseOptions {
+            reject_mixed_indentation: true,
+            indented_tags: true,
+            ..Default::default()
+        };
+        let data = "[Generic]\n\tred_hair\n  female\n[IDs]\n\t 102349\n[EmptyGroup]";
+        let mut parser = TagParser::unparsed(data.to_string(), options.clone());
+        assert_eq!(
+            parser.try_parse(),
+            Err(ParseError::MixedIndentation { line: 5 })
+        );
+        let names: Vec<&str> = parser
+            .groups()
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Generic", "IDs"]);
+        assert_eq!(
+            ParseError::MixedIndentation { line: 5 }.to_string(),
+            "line 5 is indented with both tabs and spaces"
+        );
+
+        // Tabs and spaces after the indentation are fine
+        let parser = TagParser::with_options("[A]\n\tred \t hair", options);
+        assert!(parser.error().is_none());
+
+        let parser = TagParser::from(data);
+        assert!(parser.error().is_none());
+        assert_eq!(parser.groups().len(), 3);
+    }
+
+    #[test]
+    fn raw_block_with_options_test() {
+        let options = ParseOptions {
+            raw_blocks: true,
+            lowercase_group_names: true,
+            ..Default::default()
+        };
+        let parser = TagParser::with_options("[Generic] # keep me\nred", options);
+        assert_eq!(parser.to_string(), "[Generic] # keep me\nred\n");
+
+        let options = ParseOptions {
+            raw_blocks: true,
+            tag_trim_chars: vec!['*'],
+            ..Default::default()
+        };
+        let mut parser = TagParser::with_options("[A] # keep me\n*red*", options);
+        assert_eq!(parser.to_string(), "[A] # keep me\n*red*\n");
+
+        parser.add_tag("A", "blue");
+        assert_eq!(parser.to_string(), "[A]\nred\nblue\n");
     }
 }
@@ -0,0 +1,170 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::{header_names, is_tag_line, join_header, tag_text, ParseError, TagParser};
+
+/// A read-only tag file mapped into memory, for files too large to copy into a `TagParser`.
+///
+/// Groups borrow their names and tags straight from the mapping, so no text is copied, except
+/// for the names of headers that continue over several lines. The file is read with the default parse options, using the same header and tag rules as
+/// [`TagParser`]. Changing the file while it is mapped is
+/// undefined behavior, so the file must not be modified until the parser is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use tag_parser::TagParser;
+///
+/// let parser = TagParser::from_mmap(Path::new("tags.txt")).unwrap();
+/// for group in parser.groups().unwrap() {
+///     println!("{}: {}", group.name, group.tags.len());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MappedTagParser {
+    /// The mapped contents of the file, checked to be valid UTF-8.
+    map: Mmap,
+}
+
+/// A group whose name and tags borrow from a [`MappedTagParser`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedGroup<'a> {
+    /// The name of the group, only owned if its header continues over several lines.
+    pub name: Cow<'a, str>,
+    /// Other names the group can be looked up by.
+    pub aliases: Vec<Cow<'a, str>>,
+    /// A list of tags that belong to the group.
+    pub tags: Vec<&'a str>,
+}
+
+impl MappedTagParser {
+    /// Returns the mapped contents of the file.
+    pub fn data(&self) -> &str {
+        // SAFETY: The mapping was checked to be valid UTF-8 when it was created
+        unsafe { std::str::from_utf8_unchecked(&self.map) }
+    }
+
+    /// Parses the mapped file and returns its groups.
+    ///
+    /// The data is parsed again on every call, so keep the result instead of calling this
+    /// repeatedly.
+    ///
+    /// returns: The groups, or [`ParseError::UnclosedHeader`] for a header without a closing
+    /// `]`, as with [`TagParser::try_parse`].
+    pub fn groups(&self) -> Result<Vec<MappedGroup<'_>>, ParseError> {
+        let mut groups = Vec::new();
+        // `None` while there is no group, or the current header has no name
+        let mut group: Option<MappedGroup> = None;
+
+        let mut lines = (1..).zip(self.data().lines());
+        while let Some((number, line)) = lines.next() {
+            if line.starts_with('[') {
+                groups.extend(group.take());
+                let (header, _) = join_header(line, &mut lines);
+                let unclosed = ParseError::UnclosedHeader { line: number };
+                let mut names: Vec<Cow<str>> = match header {
+                    Cow::Borrowed(header) => header_names(header, false, false)
+                        .ok_or(unclosed)?
+                        .map(Cow::Borrowed)
+                        .collect(),
+                    Cow::Owned(header) => header_names(&header, false, false)
+                        .ok_or(unclosed)?
+                        .map(|name| Cow::Owned(name.to_string()))
+                        .collect(),
+                };
+                group = (!names[0].is_empty()).then(|| MappedGroup {
+                    name: names.remove(0),
+                    aliases: names
+                        .into_iter()
+                        .filter(|alias| !alias.is_empty())
+                        .collect(),
+                    tags: Vec::new(),
+                });
+            } else if let (Some(group), true) = (group.as_mut(), is_tag_line(line)) {
+                group.tags.push(tag_text(line, true).trim());
+            }
+        }
+
+        groups.extend(group);
+        Ok(groups)
+    }
+}
+
+impl TagParser {
+    /// Maps a file into memory for parsing without copying it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: A path to the file containing the tags.
+    ///
+    /// returns: The mapped file, or an error if it could not be opened or mapped, or is not
+    /// valid UTF-8.
+    pub fn from_mmap(path: &Path) -> io::Result<MappedTagParser> {
+        let file = File::open(path)?;
+        // SAFETY: The mapping is read-only, and callers must not modify the file while it is
+        // mapped, as documented on `MappedTagParser`
+        let map = unsafe { Mmap::map(&file)? };
+        std::str::from_utf8(&map)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        Ok(MappedTagParser { map })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn from_mmap_test() {
+        let data = include_str!("test_data.txt");
+        let path = std::env::temp_dir().join(format!("tag_parser_mmap_{}.txt", std::process::id()));
+        fs::write(&path, data).unwrap();
+
+        let mapped = TagParser::from_mmap(&path).unwrap();
+        let groups = mapped.groups().unwrap();
+        let expected = TagParser::from(data);
+        assert_eq!(groups.len(), expected.groups().len());
+        for (group, expected) in groups.iter().zip(expected.groups()) {
+            assert_eq!(group.name, expected.name);
+            assert_eq!(group.aliases, expected.aliases);
+            assert_eq!(group.tags, expected.tags);
+        }
+        assert_eq!(mapped.data(), data);
+
+        let data = "[Very Long \\\n  Name | Alias]\nred\n[Short]\nblue";
+        fs::write(&path, data).unwrap();
+        let mapped = TagParser::from_mmap(&path).unwrap();
+        let groups = mapped.groups().unwrap();
+        let expected = TagParser::from(data);
+        assert_eq!(groups.len(), 2);
+        for (group, expected) in groups.iter().zip(expected.groups()) {
+            assert_eq!(group.name, expected.name);
+            assert_eq!(group.aliases, expected.aliases);
+            assert_eq!(group.tags, expected.tags);
+        }
+        assert_eq!(groups[0].name, "Very Long Name");
+        assert!(matches!(groups[1].name, Cow::Borrowed("Short")));
+        drop(mapped);
+
+        fs::write(&path, "[Generic]\nred_hair\n[IDs\n102349").unwrap();
+        let mapped = TagParser::from_mmap(&path).unwrap();
+        assert_eq!(
+            mapped.groups().err(),
+            Some(ParseError::UnclosedHeader { line: 3 })
+        );
+        drop(mapped);
+
+        fs::write(&path, [b'[', 0xff, b']']).unwrap();
+        let error = TagParser::from_mmap(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
+}